@@ -0,0 +1,166 @@
+//! github style admonitions, a blockquote starting with `[!NOTE]` on its own line
+use pulldown_cmark::{Event, Tag};
+use serde::Deserialize;
+
+/// the kinds github supports, `[!note]` works too
+const KINDS: [&str; 5] = ["note", "tip", "important", "warning", "caution"];
+
+/// `[markdown.admonition_classes]`, the defaults give the same html as the `{% callout %}` tag
+/// `<div class="callout callout-note"><p class="callout-title">Note</p>...</div>`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdmonitionClasses {
+    /// on every admonition along with the one for its kind
+    pub class: String,
+    pub title: String,
+    pub note: String,
+    pub tip: String,
+    pub important: String,
+    pub warning: String,
+    pub caution: String,
+}
+
+impl Default for AdmonitionClasses {
+    fn default() -> Self {
+        AdmonitionClasses {
+            class: String::from("callout"),
+            title: String::from("callout-title"),
+            note: String::from("callout-note"),
+            tip: String::from("callout-tip"),
+            important: String::from("callout-important"),
+            warning: String::from("callout-warning"),
+            caution: String::from("callout-caution"),
+        }
+    }
+}
+
+impl AdmonitionClasses {
+    fn kind(&self, kind: &str) -> &str {
+        match kind {
+            "note" => &self.note,
+            "tip" => &self.tip,
+            "important" => &self.important,
+            "warning" => &self.warning,
+            _ => &self.caution,
+        }
+    }
+
+    fn start(&self, kind: &str) -> String {
+        let mut title = kind.to_string();
+        title[..1].make_ascii_uppercase();
+        format!(
+            "<div class=\"{} {}\">\n<p class=\"{}\">{}</p>\n",
+            self.class,
+            self.kind(kind),
+            self.title,
+            title
+        )
+    }
+}
+
+/// the kind if `events` (just after the blockquote starts) begin with a marker, along with how
+/// many events it took up and whether that was the whole paragraph
+fn marker(events: &[Event]) -> Option<(&'static str, usize, bool)> {
+    if events.first() != Some(&Event::Start(Tag::Paragraph)) {
+        return None;
+    }
+    // pulldown-cmark splits "[!NOTE]" into a few text events
+    let mut text = String::new();
+    let mut i = 1;
+    while let Some(Event::Text(t)) = events.get(i) {
+        text += t;
+        i += 1;
+    }
+    let paragraph_ends = match events.get(i) {
+        Some(Event::SoftBreak) | Some(Event::HardBreak) => false,
+        Some(Event::End(Tag::Paragraph)) => true,
+        _ => return None,
+    };
+
+    let text = text.trim().to_ascii_lowercase();
+    let kind = KINDS.iter().find(|kind| text == format!("[!{}]", kind))?;
+    Some((kind, i + 1, paragraph_ends))
+}
+
+/// turns the blockquotes into `<div>`s, anything that isn't a marker is left as a blockquote
+pub fn admonitions<'a>(events: Vec<Event<'a>>, classes: &AdmonitionClasses) -> Vec<Event<'a>> {
+    let mut output = Vec::with_capacity(events.len());
+    // whether each blockquote that's open became an admonition
+    let mut quotes: Vec<bool> = Vec::new();
+    let mut i = 0;
+    while i < events.len() {
+        match &events[i] {
+            Event::Start(Tag::BlockQuote) => match marker(&events[i + 1..]) {
+                Some((kind, used, paragraph_ends)) => {
+                    output.push(Event::Html(classes.start(kind).into()));
+                    if !paragraph_ends {
+                        output.push(Event::Start(Tag::Paragraph));
+                    }
+                    quotes.push(true);
+                    i += used;
+                }
+                None => {
+                    output.push(events[i].clone());
+                    quotes.push(false);
+                }
+            },
+            Event::End(Tag::BlockQuote) => {
+                if quotes.pop() == Some(true) {
+                    output.push(Event::Html("</div>\n".into()));
+                } else {
+                    output.push(events[i].clone());
+                }
+            }
+            event => output.push(event.clone()),
+        }
+        i += 1;
+    }
+    output
+}
+
+#[cfg(test)]
+mod admonition_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::{html, Parser};
+
+    fn render(md: &str, classes: &AdmonitionClasses) -> String {
+        let mut output = String::new();
+        html::push_html(
+            &mut output,
+            admonitions(Parser::new(md).collect(), classes).into_iter(),
+        );
+        output
+    }
+
+    #[test]
+    fn markers() {
+        let classes = AdmonitionClasses::default();
+        assert_eq!(
+            "<div class=\"callout callout-note\">\n<p class=\"callout-title\">Note</p>\n<p>Useful <em>info</em>.</p>\n</div>\n",
+            render("> [!NOTE]\n> Useful *info*.", &classes)
+        );
+        assert_eq!(
+            "<div class=\"callout callout-warning\">\n<p class=\"callout-title\">Warning</p>\n<p>a</p>\n<blockquote>\n<p>b</p>\n</blockquote>\n</div>\n",
+            render("> [!warning]\n>\n> a\n>> b", &classes)
+        );
+        assert_eq!(
+            "<blockquote>\n<p>[!NOTE] a\nb</p>\n</blockquote>\n<blockquote>\n<p>[!CATS]</p>\n</blockquote>\n",
+            render("> [!NOTE] a\n> b\n\n> [!CATS]", &classes)
+        );
+    }
+
+    #[test]
+    fn classes() {
+        let classes = AdmonitionClasses {
+            class: "alert".to_string(),
+            title: "alert-heading".to_string(),
+            tip: "alert-success".to_string(),
+            ..AdmonitionClasses::default()
+        };
+        assert_eq!(
+            "<div class=\"alert alert-success\">\n<p class=\"alert-heading\">Tip</p>\n<p>a</p>\n</div>\n",
+            render("> [!TIP]\n> a", &classes)
+        );
+    }
+}
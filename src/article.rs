@@ -1,23 +1,31 @@
-use crate::parse::{
-    parse_error_message, parse_key, parse_value_boolean, parse_value_list, parse_value_string,
-    parse_value_time, ParseError,
-};
+use crate::parse::{parse_error_message, FrontMatter, ParseError};
 
+use crate::admonition::admonitions;
 use crate::error::CustomError;
+use crate::highlight::{highlight_code_blocks, Highlighter};
+use crate::site::{MarkdownConfig, SiteConfig};
+use crate::toc::{self, anchor_headings, Heading};
+use crate::typography::smart_punctuation;
+use crate::util;
+use log::info;
 #[cfg(not(test))]
 use log::warn;
 
 #[cfg(test)]
 use std::println as warn;
 
-use chrono::NaiveDateTime;
-use pulldown_cmark::{html, Options, Parser};
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use pulldown_cmark::{html, Event, Parser};
 use std::{
+    collections::HashSet,
     fs::File,
     io::{BufRead, BufReader},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
+/// besides markdown, files with these extensions are pages if they start with front matter
+pub const PAGE_EXTENSIONS: [&str; 4] = ["html", "xml", "txt", "json"];
+
 #[derive(Debug, PartialEq)]
 pub struct Config {
     pub layout: String,
@@ -27,8 +35,32 @@ pub struct Config {
     pub permalink: String,
     pub categories: Vec<String>,
     pub tags: Vec<String>,
+    /// `titlebar` (or `visible`), `page.config.visible` for layouts that show a title bar
     pub visible: bool,
-    pub date: Option<NaiveDateTime>,
+    /// unlisted articles are rendered but left out of `global.articles`, tags and cats
+    pub listed: bool,
+    /// unpublished articles aren't rendered at all
+    pub published: bool,
+    pub date: Option<DateTime<FixedOffset>>,
+    /// only built with `mole build --drafts`
+    pub draft: bool,
+    /// left out of the build from this date onwards
+    pub expires: Option<DateTime<FixedOffset>>,
+    /// the file name without the extension or the date prefix
+    pub slug: String,
+    /// whether the file name started with YYYY-MM-DD-
+    pub dated_file_name: bool,
+    /// `[markdown]` from mole.toml with the article's `markdown:` overrides on top
+    pub markdown: MarkdownConfig,
+    /// `toc: false` leaves `page.toc` empty, headings still get their ids
+    pub toc: bool,
+    /// set for pages like `feed.xml`, they skip markdown, keep their extension in the url,
+    /// don't need a title or layout and are unlisted by default
+    pub page_extension: Option<String>,
+    /// the `[collections.<name>]` the article's directory belongs to
+    pub collection: Option<String>,
+    /// any keys mole doesn't know about, these end up in `page.config.*`
+    pub custom: liquid::Object,
 }
 
 impl Default for Config {
@@ -42,20 +74,96 @@ impl Default for Config {
             categories: Vec::new(),
             tags: Vec::new(),
             visible: false,
+            listed: true,
+            published: true,
             date: None,
+            draft: false,
+            expires: None,
+            slug: String::from(""),
+            dated_file_name: false,
+            markdown: MarkdownConfig::default(),
+            toc: true,
+            page_extension: None,
+            collection: None,
+            custom: liquid::Object::new(),
         }
     }
 }
 
 impl Config {
     fn is_valid(&self) -> bool {
-        !(self.layout.is_empty() || self.title.is_empty())
+        !self.is_markdown() || !(self.layout.is_empty() || self.title.is_empty())
+    }
+
+    pub fn is_markdown(&self) -> bool {
+        self.page_extension.is_none()
+    }
+
+    /// why the article shouldn't be built, `drafts` and `future` are the `mole build` flags
+    pub fn skip_reason(&self, now: &DateTime<Utc>, drafts: bool, future: bool) -> Option<String> {
+        if !self.published {
+            return Some("unpublished".to_string());
+        }
+        if self.draft && !drafts {
+            return Some("draft".to_string());
+        }
+        if let Some(date) = &self.date {
+            if !future && date > now {
+                return Some(format!("future date {}", date.to_rfc3339()));
+            }
+        }
+        if let Some(expires) = &self.expires {
+            if expires <= now {
+                return Some(format!("expired {}", expires.to_rfc3339()));
+            }
+        }
+        None
+    }
+
+    /// `page.config`, custom keys can't overwrite the ones mole sets
+    fn to_liquid(&self) -> liquid::Object {
+        let mut config = liquid::object!({
+            "title": self.title,
+            "description": self.description,
+            "tags": self.tags,
+            "categories": self.categories,
+            "listed": self.listed,
+            "visible": self.visible,
+            "published": self.published,
+            "layout": self.layout,
+            "slug": self.slug,
+            "draft": self.draft,
+        });
+
+        if let Some(date) = &self.date {
+            config.insert("date".into(), util::to_liquid_date(date));
+        }
+        if let Some(expires) = &self.expires {
+            config.insert("expires".into(), util::to_liquid_date(expires));
+        }
+        if let Some(collection) = &self.collection {
+            config.insert(
+                "collection".into(),
+                liquid::model::Value::scalar(collection.clone()),
+            );
+        }
+
+        for (key, value) in &self.custom {
+            if !config.contains_key(key.as_str()) {
+                config.insert(key.clone(), value.clone());
+            }
+        }
+
+        config
     }
 }
 
 #[derive(Debug)]
 pub struct Article {
     pub template: String,
+    /// the content before `excerpt_separator` or the first paragraph,
+    /// goes through the same liquid and markdown steps as the template
+    pub excerpt: String,
     pub config: Config,
     pub url: String,
     pub config_liquid: liquid::Object,
@@ -66,104 +174,214 @@ pub struct Article {
 /// BufReader<R> can improve the speed of programs that make small and repeated read calls to the same file or network socket.
 /// It does not help when reading very large amounts at once, or reading just one or a few times.
 /// It also provides no advantage when reading from a source that is already in memory, like a Vec<u8>.
-pub fn parse(data: BufReader<File>, path: &PathBuf) -> Result<(Config, String), ParseError> {
-    let mut found_config = false;
-    let mut line_n = 1;
-    let mut config = Config::default();
+///
+/// `site.strict()` turns unknown keys into errors rather than keeping them as custom values
+pub fn parse(
+    data: BufReader<File>,
+    path: &PathBuf,
+    site: &SiteConfig,
+) -> Result<(Config, String), ParseError> {
+    let page_extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .filter(|extension| PAGE_EXTENSIONS.contains(extension))
+        .map(String::from);
+    // pages like feed.xml shouldn't show up in the article listings unless they ask to
+    let mut config = Config {
+        markdown: site.markdown.clone(),
+        listed: page_extension.is_none(),
+        page_extension,
+        ..Config::default()
+    };
+    let mut lines = data.lines();
 
-    // we set the defaults here e.g. default_layout: "default"
-    // therefore when we get default_layout: "" then it overwrites the default
-    let lines = data.lines();
+    let read_error = |err: std::io::Error, line_n: usize| {
+        ParseError::InvalidValue(parse_error_message(
+            &err.to_string(),
+            path,
+            "",
+            0,
+            10,
+            line_n,
+        ))
+    };
 
-    let mut body = "".to_string();
+    // '---' is yaml and '+++' is toml like hugo uses
+    let delimiter = match lines.next() {
+        Some(line) => {
+            let line = line.map_err(|e| read_error(e, 1))?;
+            if line == "---" || line == "+++" {
+                line
+            } else {
+                return Err(ParseError::InvalidConfig(parse_error_message(
+                    "configuration needs to start with '---' for the first line (or '+++' for toml)",
+                    path,
+                    &line,
+                    0,
+                    line.len(),
+                    1,
+                )));
+            }
+        }
+        None => {
+            return Err(ParseError::InvalidConfig(
+                "no at '---' for the last line of the configuration".into(),
+            ))
+        }
+    };
+
+    // everything between the two delimiter lines is handed over to the yaml/toml parser in one go
+    // so multi-line values, nested maps and lists all work
+    let mut front = Vec::new();
     let mut reached_end = false;
+    for line in &mut lines {
+        let line = line.map_err(|e| read_error(e, front.len() + 2))?;
+        if line == delimiter {
+            reached_end = true;
+            break;
+        }
+        front.push(line);
+    }
+
+    if !reached_end {
+        return Err(ParseError::InvalidConfig(format!(
+            "no at '{}' for the last line of the configuration",
+            delimiter
+        )));
+    }
 
+    let mut body = "".to_string();
     for line in lines {
-        let line = match &line {
-            Ok(line) => line,
-            Err(err) => Err(ParseError::InvalidValue(parse_error_message(
-                &err.to_string(),
-                path,
-                "",
-                0,
-                10,
-                line_n,
-            )))?,
-        };
-        if !found_config && line == "---" {
-            found_config = true;
-            line_n += 1;
-        } else if found_config && line == "---" {
-            reached_end = true;
-            found_config = false;
-            line_n += 1;
-        } else if reached_end {
-            body += &line;
-            body += "\n";
-        } else if found_config {
-            let (key, rest) = parse_key(&line, path, line, line_n)?;
-            match key {
-                // match each thing but then need to work out how to map it....
-                // maybe look into the from string implementation???
-                "layout" => {
-                    config.layout = parse_value_string(rest.trim(), path, line, line_n)?.to_string()
-                }
-                "base_layout" => {
-                    config.base_layout =
-                        parse_value_string(rest.trim(), path, line, line_n)?.to_string()
-                }
-                "title" => {
-                    config.title = parse_value_string(rest.trim(), path, line, line_n)?.to_string()
-                }
-                "description" => {
-                    config.description =
-                        parse_value_string(rest.trim(), path, line, line_n)?.to_string()
-                }
-                "permalink" => {
-                    config.permalink =
-                        parse_value_string(rest.trim(), path, line, line_n)?.to_string()
-                }
-                "categories" => {
-                    config.categories = parse_value_list(rest.trim(), path, line, line_n)?
+        let line = line.map_err(|e| read_error(e, 0))?;
+        body += &line;
+        body += "\n";
+    }
+
+    // we set the defaults here e.g. default_layout: "default"
+    // therefore when we get default_layout: "" then it overwrites the default
+    let mut front = if delimiter == "+++" {
+        FrontMatter::toml(front, path, 2)?
+    } else {
+        FrontMatter::yaml(front, path, 2)?
+    };
+    // `---` straight after `---` is fine for something like robots.txt
+    if front.is_empty() && config.is_markdown() {
+        return Err(ParseError::InvalidConfig(format!(
+            "empty config no key value pairs found in {}",
+            path.to_str().unwrap()
+        )));
+    }
+
+    // mole.toml [[defaults]] only fill in what the article left out, this has to happen
+    // before the checks at the end so that an inherited layout counts
+    for rule in site.defaults_for(path) {
+        for (key, value) in &rule.values {
+            if let Some(key) = key.as_str() {
+                if front.set_default(key, value.clone(), &rule.describe()) {
+                    info!(
+                        "{:?} '{}' set by defaults rule {}",
+                        path,
+                        key,
+                        rule.describe()
+                    );
                 }
-                "tags" => config.tags = parse_value_list(rest.trim(), path, line, line_n)?,
-                "titlebar" => {
-                    config.visible = parse_value_boolean(rest.trim(), path, line, line_n)?
+            }
+        }
+    }
+
+    // a collection's layout and permalink come after any [[defaults]]
+    if let Some(collection) = site.collection_for(path) {
+        config.collection = Some(collection.name.clone());
+        let rule = format!("[collections.{}]", collection.name);
+        for (key, value) in &[
+            ("layout", &collection.layout),
+            ("permalink", &collection.permalink),
+        ] {
+            if let Some(value) = value {
+                front.set_default(key, serde_yaml::Value::String(value.clone()), &rule);
+            }
+        }
+    }
+
+    for key in front.keys()? {
+        match key.as_str() {
+            "layout" => config.layout = front.string(&key)?,
+            "base_layout" => config.base_layout = front.string(&key)?,
+            "title" => config.title = front.string(&key)?,
+            "description" => config.description = front.string(&key)?,
+            "permalink" => config.permalink = front.string(&key)?,
+            "categories" => config.categories = front.list(&key)?,
+            "tags" => config.tags = front.list(&key)?,
+            "titlebar" | "visible" => config.visible = front.boolean(&key)?,
+            "listed" => config.listed = front.boolean(&key)?,
+            "unlisted" => config.listed = !front.boolean(&key)?,
+            "published" => config.published = front.boolean(&key)?,
+            "date" => config.date = Some(front.time(&key, &site.timezone)?),
+            "draft" => config.draft = front.boolean(&key)?,
+            "expires" => config.expires = Some(front.time(&key, &site.timezone)?),
+            "toc" => config.toc = front.boolean(&key)?,
+            "markdown" => match front.get(&key) {
+                Some(serde_yaml::Value::Mapping(extensions)) => {
+                    for (name, on) in extensions {
+                        match (name.as_str(), on.as_bool()) {
+                            (Some(name), Some(on)) => {
+                                if !config.markdown.set(name, on) {
+                                    return Err(ParseError::InvalidValue(front.error(
+                                        &key,
+                                        &format!("unknown markdown extension {:?}, expected one of tables, footnotes, strikethrough, tasklists, smart_punctuation or admonitions", name),
+                                    )));
+                                }
+                            }
+                            _ => {
+                                return Err(ParseError::InvalidValue(front.error(
+                                    &key,
+                                    "expected extensions set to true or false e.g. `markdown: { footnotes: false }`",
+                                )))
+                            }
+                        }
+                    }
                 }
-                "date" => config.date = Some(parse_value_time(rest.trim(), path, line, line_n)?),
                 _ => {
-                    return Err(ParseError::InvalidKey(parse_error_message(
-                        "unknown key",
-                        path,
-                        line,
-                        0,
-                        line.len() - 1,
-                        line_n,
+                    return Err(ParseError::InvalidValue(front.error(
+                        &key,
+                        "expected a map of extensions e.g. `markdown: { footnotes: false }`",
                     )))
                 }
+            },
+            _ => {
+                if site.strict() {
+                    return Err(ParseError::InvalidKey(front.error(&key, "unknown key")));
+                }
+                if let Some(value) = front.get(&key) {
+                    config.custom.insert(
+                        key.clone().into(),
+                        util::yaml_to_liquid(value, &site.timezone),
+                    );
+                }
             }
-            line_n += 1;
-        } else {
-            return Err(ParseError::InvalidConfig(parse_error_message(
-                "configuration needs to start with '---' for the first line",
+        }
+    }
+
+    // jekyll style `2020-01-02-title.md`, the date in the config wins over the file name
+    let (file_date, slug) = split_file_name(path);
+    config.slug = slug;
+    if let Some(file_date) = file_date {
+        config.dated_file_name = true;
+        match config.date {
+            Some(date) if date.naive_local().date() != file_date => warn!(
+                "{:?} date in the config {} doesn't match the date in the file name {}, using {}",
                 path,
-                line,
-                0,
-                line.len(),
-                line_n,
-            )));
+                date.naive_local().date(),
+                file_date,
+                date.naive_local().date()
+            ),
+            Some(_) => {}
+            None => config.date = site.timezone.localise(&file_date.and_hms(0, 0, 0)),
         }
     }
+
     if config.is_valid() {
         return Ok((config, body));
-    } else if line_n == 2 {
-        return Err(ParseError::InvalidConfig(
-            format!("empty config no key value pairs found in {}", "test.txt").into(),
-        ));
-    } else if !reached_end {
-        return Err(ParseError::InvalidConfig(
-            "no at '---' for the last line of the configuration".into(),
-        ));
     } else if config.title.is_empty() {
         return Err(ParseError::InvalidConfig(
             "missing configuration 'title' field".into(),
@@ -176,37 +394,279 @@ pub fn parse(data: BufReader<File>, path: &PathBuf) -> Result<(Config, String),
     }
 }
 
+/// whether the first line is `---` or `+++`, anything else that isn't markdown is left alone
+pub fn has_front_matter(path: &Path) -> bool {
+    match File::open(path).map(|f| BufReader::new(f).lines().next()) {
+        Ok(Some(Ok(line))) => line == "---" || line == "+++",
+        _ => false,
+    }
+}
+
+/// `2020-01-02-cats-and-dogs.md` -> (2020-01-02, "cats-and-dogs")
+fn split_file_name(path: &Path) -> (Option<NaiveDate>, String) {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("")
+        .to_string();
+
+    if let (Some(date), Some(rest)) = (stem.get(..10), stem.get(10..)) {
+        if rest.len() > 1 && rest.starts_with('-') {
+            if let Ok(date) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+                return (Some(date), rest[1..].to_string());
+            }
+        }
+    }
+    (None, stem)
+}
+
+/// the outermost liquid blocks, `{% if %}...{% endif %}` or a shortcode block, as byte
+/// ranges. A tag is taken to open a block if there's an `end` tag for it somewhere
+fn liquid_blocks(content: &str) -> Vec<(usize, usize)> {
+    let mut tags = Vec::new();
+    let mut rest = 0;
+    while let Some(start) = content[rest..].find("{%") {
+        let start = rest + start;
+        let end = match content[start..].find("%}") {
+            Some(end) => start + end + 2,
+            None => break,
+        };
+        let name = content[start + 2..end - 2]
+            .trim_matches('-')
+            .split_whitespace()
+            .next()
+            .unwrap_or("");
+        tags.push((start, end, name));
+        rest = end;
+    }
+
+    let closed: HashSet<&str> = tags
+        .iter()
+        .filter_map(|(_, _, name)| name.strip_prefix("end"))
+        .collect();
+    let mut open = Vec::new();
+    let mut blocks = Vec::new();
+    for (start, end, name) in tags {
+        if name.starts_with("end") && !open.is_empty() {
+            let start = open.pop().unwrap();
+            if open.is_empty() {
+                blocks.push((start, end));
+            }
+        } else if closed.contains(name) {
+            open.push(start);
+        }
+    }
+    if let Some(start) = open.first() {
+        blocks.push((*start, content.len()));
+    }
+    blocks
+}
+
+/// the content before `separator`, or the first paragraph if there isn't one. It's parsed as
+/// liquid on its own so it's never cut inside a liquid block
+fn excerpt(content: &str, separator: &str) -> String {
+    let blocks = liquid_blocks(content);
+    let outside = |index: usize| {
+        !blocks
+            .iter()
+            .any(|(start, end)| *start < index && index < *end)
+    };
+
+    if let Some((index, _)) = content
+        .match_indices(separator)
+        .find(|(index, _)| outside(*index))
+    {
+        return content[..index].trim_end().to_string();
+    }
+
+    let mut end = 0;
+    for line in content.split('\n') {
+        if line.trim().is_empty() && outside(end) {
+            break;
+        }
+        end += line.len() + 1;
+    }
+    content[..end.min(content.len())]
+        .lines()
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// the passes over the event stream happen between parsing and writing the html, also gives
+/// back the headings in the order they appear
+pub fn markdown(
+    content: &str,
+    config: &MarkdownConfig,
+    highlighter: Option<&Highlighter>,
+) -> (String, Vec<Heading>) {
+    let mut events: Vec<Event> = Parser::new_ext(content, config.options()).collect();
+    if config.admonitions {
+        events = admonitions(events, &config.admonition_classes);
+    }
+    // before highlighting, which turns code blocks into html
+    if config.smart_punctuation {
+        events = smart_punctuation(events);
+    }
+    events = highlight_code_blocks(events, highlighter);
+    let (events, headings) = anchor_headings(events);
+
+    // Write to String buffer.
+    let mut html_output = String::new();
+    html::push_html(&mut html_output, events.into_iter());
+    (html_output, headings)
+}
+
+/// fills in `:year`, `:month`, `:day`, `:short_year`, `:i_month`, `:i_day`, `:title`, `:slug`
+/// and `:categories`, anything else after a ':' is left as is. The url always starts with '/'
+fn expand_permalink(pattern: &str, config: &Config, path: &PathBuf) -> Result<String, ParseError> {
+    let mut url = String::new();
+    let mut rest = pattern;
+    while let Some(start) = rest.find(':') {
+        url += &rest[..start];
+        rest = &rest[start + 1..];
+        let end = rest
+            .find(|c: char| !(c.is_ascii_lowercase() || c == '_'))
+            .unwrap_or(rest.len());
+        let name = &rest[..end];
+
+        let date_format = match name {
+            "year" => Some("%Y"),
+            "month" => Some("%m"),
+            "day" => Some("%d"),
+            "short_year" => Some("%y"),
+            "i_month" => Some("%-m"),
+            "i_day" => Some("%-d"),
+            _ => None,
+        };
+
+        if let Some(format) = date_format {
+            match &config.date {
+                Some(date) => url += &date.format(format).to_string(),
+                None => {
+                    return Err(ParseError::InvalidConfig(format!(
+                        "{:?} permalink {:?} uses :{} but the article has no date",
+                        path, pattern, name
+                    )))
+                }
+            }
+        } else {
+            match name {
+                "title" | "slug" => {
+                    // a title like "猫" has nothing left once it's slugified, fall back to
+                    // the file name rather than writing every one of them to "/.html"
+                    let mut slug = if name == "title" {
+                        util::slugify(&config.title)
+                    } else {
+                        String::new()
+                    };
+                    if slug.is_empty() {
+                        slug = util::slugify(&config.slug);
+                    }
+                    if slug.is_empty() {
+                        return Err(ParseError::InvalidConfig(format!(
+                            "{:?} permalink {:?} uses :{} but there's nothing left of it in a url, set a permalink without it",
+                            path, pattern, name
+                        )));
+                    }
+                    url += &slug;
+                }
+                "categories" => {
+                    url += &config
+                        .categories
+                        .iter()
+                        .map(|cat| util::slugify(cat))
+                        .filter(|cat| !cat.is_empty())
+                        .collect::<Vec<_>>()
+                        .join("/")
+                }
+                _ => {
+                    url.push(':');
+                    url += name;
+                }
+            }
+        }
+        rest = &rest[end..];
+    }
+    url += rest;
+
+    // an empty placeholder like :categories leaves a '//' behind
+    while url.contains("//") {
+        url = url.replace("//", "/");
+    }
+    if !url.starts_with('/') {
+        url.insert(0, '/');
+    }
+    Ok(url)
+}
+
 impl Article {
+    /// where the article is written to under `dest`, urls ending in '/' become index.html
+    pub fn output_path(&self, dest: &Path) -> PathBuf {
+        let mut path = dest.join(self.url.trim_start_matches('/').replace("%20", " "));
+        if self.url.ends_with('/') {
+            path.push("index.html");
+        }
+        path
+    }
+
     /// header is in a --- --- block with new lines
-    /// the rest of the doc is template in markdown
-    pub fn parse(md: BufReader<File>, path: &PathBuf) -> Result<Article, ParseError> {
+    /// the rest of the doc is template in markdown, `dir` is the articles directory it was
+    /// found in
+    pub fn parse(
+        md: BufReader<File>,
+        path: &PathBuf,
+        dir: &Path,
+        site: &SiteConfig,
+    ) -> Result<Article, ParseError> {
         // markdown parsing NOTE: we are assuming that we are dealing with markdown hear!!!
-        let (config, content) = parse(md, path)?;
+        let (config, content) = parse(md, path, site)?;
 
         let template = content.trim().to_string();
+        let excerpt = excerpt(&template, &site.excerpt_separator);
 
-        let url: String = if config.permalink.is_empty() {
-            format!("{}.html", config.title)
+        // an article's own permalink wins, the site one only makes sense for dated articles.
+        // Pages keep their path in `dir` as it is so 404.html isn't turned into /404/
+        let url = if config.permalink.is_empty() && !config.is_markdown() {
+            let relative = path.strip_prefix(dir).unwrap_or(path);
+            relative.iter().fold(String::new(), |url, part| {
+                url + "/" + &part.to_string_lossy()
+            })
         } else {
-            config.permalink.clone() // messy.... argh!!!
+            let pattern = if !config.permalink.is_empty() {
+                config.permalink.as_str()
+            } else if let (Some(permalink), Some(_)) = (&site.permalink, &config.date) {
+                permalink.as_str()
+            } else if config.dated_file_name {
+                if site.pretty_urls {
+                    "/:slug/"
+                } else {
+                    "/:slug.html"
+                }
+            } else if site.pretty_urls {
+                "/:title/"
+            } else {
+                "/:title.html"
+            };
+            expand_permalink(pattern, &config, path)?
         }
         .replace(" ", "%20");
 
+        // global.articles is made from this before anything is rendered, so the excerpt
+        // there only has the markdown step (liquid in it is left as is)
         let config_liquid = liquid::object!({
             "content": template,
-            "config": liquid::object!({
-                "title": config.title,
-                "description": config.description,
-                "tags": config.tags,
-                "categories": config.categories,
-                "visible": config.visible,
-                "layout": config.layout,
-            }),
+            "excerpt": if config.is_markdown() {
+                markdown(&excerpt, &config.markdown, None).0
+            } else {
+                excerpt.clone()
+            },
+            "config": config.to_liquid(),
             "url":url,
         });
 
         return Ok(Article {
             template,
+            excerpt,
             config,
             url,
             config_liquid,
@@ -216,41 +676,58 @@ impl Article {
     fn pre_render(
         mut self,
         globals: &liquid::Object,
+        site: &liquid::Object,
         liquid_parser: &liquid::Parser,
+        highlighter: Option<&Highlighter>,
         md: bool,
     ) -> Result<Self, CustomError> {
         // hack do proper error handling!!!
 
-        let template = liquid_parser
-            .parse(&self.template)?
-            .render(&liquid::object!({
-                "global": globals,
-                "page": self.config_liquid,
-                "layout": self.config.layout
-            }))?;
-
-        self.template = if md {
-            let parser = Parser::new_ext(&template, Options::empty());
-
-            // Write to String buffer.
-            let mut template = String::new();
-            html::push_html(&mut template, parser);
-            template
-        } else {
-            template
+        let globals = liquid::object!({
+            "global": globals,
+            "site": site,
+            "page": self.config_liquid,
+            "layout": self.config.layout
+        });
+        let template = liquid_parser.parse(&self.template)?.render(&globals)?;
+        // the article is still built if the excerpt can't be
+        let excerpt = match liquid_parser
+            .parse(&self.excerpt)
+            .and_then(|excerpt| excerpt.render(&globals))
+        {
+            Ok(excerpt) => excerpt,
+            Err(e) => {
+                warn!("{:?} excerpt left empty: {}", self.url, e);
+                String::new()
+            }
         };
 
+        // only the markdown pass knows the headings, pages never get one
+        let markdown_page = self.config.is_markdown();
+        let mut contents = Vec::new();
+        if md && markdown_page {
+            let (template, headings) = markdown(&template, &self.config.markdown, highlighter);
+            self.template = template;
+            self.excerpt = markdown(&excerpt, &self.config.markdown, highlighter).0;
+            if self.config.toc {
+                contents = toc::tree(headings);
+            }
+        } else {
+            self.template = template;
+            self.excerpt = excerpt;
+        }
+
         self.config_liquid = liquid::object!({
             "content": self.template,
-            "config": liquid::object!({
-                "title": self.config.title,
-                "description": self.config.description,
-                "tags": self.config.tags,
-                "categories": self.config.categories,
-                "visible": self.config.visible,
-                "layout": self.config.layout,
-            }),
+            "excerpt": if md || !markdown_page {
+                self.excerpt.clone()
+            } else {
+                markdown(&self.excerpt, &self.config.markdown, highlighter).0
+            },
+            "config": self.config.to_liquid(),
             "url":self.url,
+            "toc_html": toc::to_html(&contents),
+            "toc": contents,
         });
 
         Ok(self)
@@ -259,8 +736,13 @@ impl Article {
     fn render(
         &self,
         globals: &liquid::Object,
+        site: &liquid::Object,
         parser: &liquid::Parser,
     ) -> Result<String, CustomError> {
+        // a page without a layout is just its own content
+        if !self.config.is_markdown() && self.config.layout.is_empty() {
+            return Ok(self.template.clone());
+        }
         let template = if self.config.base_layout.is_empty() {
             if self.config.layout.is_empty() {
                 warn!("no base layout found");
@@ -288,27 +770,32 @@ impl Article {
 
         Ok(template.render(&liquid::object!({
             "global": globals,
+            "site": site,
             "page": self.config_liquid,
             "layout": self.config.layout
         }))?)
     }
 
+    /// `highlighter` is None when `[highlight] enabled = false`
     pub fn true_render(
         self,
         global: &liquid::Object,
+        site: &liquid::Object,
         parser: &liquid::Parser,
+        highlighter: Option<&Highlighter>,
     ) -> Result<String, CustomError> {
         Ok(self
-            .pre_render(&global, parser, false)?
-            .pre_render(&global, parser, true)?
-            .render(&global, parser)?)
+            .pre_render(&global, site, parser, highlighter, false)?
+            .pre_render(&global, site, parser, highlighter, true)?
+            .render(&global, site, parser)?)
     }
 }
 
 #[cfg(test)]
 mod render {
 
-    use super::{Article, BufReader, CustomError, File, ParseError};
+    use super::{Article, BufReader, CustomError, File, ParseError, SiteConfig};
+    use liquid::ValueView;
     use std::io::Write;
     use tempfile;
 
@@ -316,6 +803,16 @@ mod render {
     type Partials = liquid::partials::EagerCompiler<liquid::partials::InMemorySource>;
 
     fn create_article(md: &str, path: &str) -> Result<Article, ParseError> {
+        create_article_with(md, path, &SiteConfig::default())
+    }
+
+    fn create_article_strict(md: &str, path: &str) -> Result<Article, ParseError> {
+        let mut site = SiteConfig::default();
+        site.build.strict = Some(true);
+        create_article_with(md, path, &site)
+    }
+
+    fn create_article_with(md: &str, path: &str, site: &SiteConfig) -> Result<Article, ParseError> {
         // create a temp file
         let mut f = tempfile::Builder::new()
             .rand_bytes(0)
@@ -329,6 +826,8 @@ mod render {
         Ok(Article::parse(
             BufReader::new(File::open(path).unwrap()),
             &std::path::PathBuf::from(path),
+            std::path::Path::new(""),
+            site,
         )?)
     }
 
@@ -344,12 +843,14 @@ mod render {
         for (k, v) in mocks {
             source.add(k, v);
         }
-        let parser = liquid::ParserBuilder::with_stdlib()
+        let site = SiteConfig::default();
+        let parser = liquid::ParserBuilder::with_stdlib();
+        let parser = crate::tags::register(crate::filters::register(parser, &site), &site)
             .partials(source)
             .build()
             .unwrap();
 
-        a.true_render(global, &parser)
+        a.true_render(global, &liquid::object!({ "title": "mole" }), &parser, None)
     }
 
     mod parse_tests {
@@ -417,6 +918,445 @@ mod render {
             assert_eq!("page", a.config.layout);
         }
 
+        #[test]
+        fn parse_yaml_front_matter() {
+            let a: Article = create_article(
+                "---\nlayout: page\ntitle: \"cats: and dogs\"\ndescription: >\n  cats\n  and dogs\ntags:\n  - cat\n  - dog\n---\ncat",
+                "parse_yaml_front_matter",
+            )
+            .unwrap();
+            assert_eq!("cats: and dogs", a.config.title);
+            assert_eq!("cats and dogs\n", a.config.description);
+            assert_eq!(vec!["cat", "dog"], a.config.tags);
+        }
+
+        #[test]
+        fn parse_toml_front_matter() {
+            let a: Article = create_article(
+                "+++\nlayout = \"page\"\ntitle = \"cats and dogs\"\ntags = [\"cat\", \"dog\"]\n+++\ncat",
+                "parse_toml_front_matter",
+            )
+            .unwrap();
+            assert_eq!("cat", a.template);
+            assert_eq!("cats and dogs", a.config.title);
+            assert_eq!(vec!["cat", "dog"], a.config.tags);
+        }
+
+        #[test]
+        fn custom_keys() {
+            let a: Article = create_article(
+                "---\nlayout: page\ntitle: cats\nhero_image: cat.png\nauthor:\n  name: mole\n  url: /mole\ndraft_count: 3\n---\ncat",
+                "custom_keys",
+            )
+            .unwrap();
+            let config = a.config_liquid.get("config").unwrap().as_object().unwrap();
+            assert_eq!(
+                "cat.png",
+                config.get("hero_image").unwrap().to_kstr().to_string()
+            );
+            assert_eq!(
+                "mole",
+                config
+                    .get("author")
+                    .unwrap()
+                    .as_object()
+                    .unwrap()
+                    .get("name")
+                    .unwrap()
+                    .to_kstr()
+                    .to_string()
+            );
+            assert_eq!(
+                3,
+                config
+                    .get("draft_count")
+                    .unwrap()
+                    .as_scalar()
+                    .unwrap()
+                    .to_integer()
+                    .unwrap()
+            );
+        }
+
+        #[test]
+        fn defaults_layout() {
+            let site = SiteConfig::parse(
+                "[[defaults]]\nglob = \"defaults_*\"\n[defaults.values]\nlayout = \"doc\"\ntags = [\"docs\"]\n",
+                std::path::Path::new("mole.toml"),
+            )
+            .unwrap();
+            let a: Article = create_article_with(
+                "---\ntitle: cats\ntags: cat\n---\ncat",
+                "defaults_layout",
+                &site,
+            )
+            .unwrap();
+            assert_eq!("doc", a.config.layout);
+            assert_eq!(vec!["cat"], a.config.tags);
+        }
+
+        #[test]
+        fn file_name_date() {
+            let a: Article = create_article(
+                "---\nlayout: page\ntitle: cats\n---\ncat",
+                "2020-01-02-file-name-date.md",
+            )
+            .unwrap();
+            assert_eq!("file-name-date", a.config.slug);
+            assert_eq!(
+                "2020-01-02T00:00:00+00:00",
+                a.config.date.unwrap().to_rfc3339()
+            );
+            assert_eq!("/file-name-date.html", a.url);
+        }
+
+        #[test]
+        fn file_name_date_overridden() {
+            let a: Article = create_article(
+                "---\nlayout: page\ntitle: cats\ndate: 2020-03-04 10:30\n---\ncat",
+                "2020-01-02-file-name-date-overridden.md",
+            )
+            .unwrap();
+            assert_eq!("file-name-date-overridden", a.config.slug);
+            assert_eq!(
+                "2020-03-04T10:30:00+00:00",
+                a.config.date.unwrap().to_rfc3339()
+            );
+        }
+
+        #[test]
+        fn site_timezone() {
+            let site = SiteConfig::parse(
+                "timezone = \"Europe/London\"",
+                std::path::Path::new("mole.toml"),
+            )
+            .unwrap();
+            let a: Article = create_article_with(
+                "---\nlayout: page\ntitle: cats\ndate: 2020-07-01 10:30:15\nupdated: 2020-07-02T10:00:00-05:00\n---\ncat",
+                "site_timezone",
+                &site,
+            )
+            .unwrap();
+            assert_eq!(
+                "2020-07-01T10:30:15+01:00",
+                a.config.date.unwrap().to_rfc3339()
+            );
+            let config = a.config_liquid.get("config").unwrap().as_object().unwrap();
+            assert!(config
+                .get("date")
+                .unwrap()
+                .as_scalar()
+                .unwrap()
+                .to_date_time()
+                .is_some());
+            assert!(config
+                .get("updated")
+                .unwrap()
+                .as_scalar()
+                .unwrap()
+                .to_date_time()
+                .is_some());
+        }
+
+        #[test]
+        fn skip_reason() {
+            let a: Article = create_article(
+                "---\nlayout: page\ntitle: cats\ndraft: true\ndate: 2020-01-02\nexpires: 2020-03-01\n---\ncat",
+                "skip_reason",
+            )
+            .unwrap();
+            let before = chrono::DateTime::parse_from_rfc3339("2020-01-01T00:00:00Z")
+                .unwrap()
+                .with_timezone(&chrono::Utc);
+            let during = chrono::DateTime::parse_from_rfc3339("2020-02-01T00:00:00Z")
+                .unwrap()
+                .with_timezone(&chrono::Utc);
+            let after = chrono::DateTime::parse_from_rfc3339("2020-03-01T00:00:00Z")
+                .unwrap()
+                .with_timezone(&chrono::Utc);
+
+            assert_eq!(
+                Some("draft".to_string()),
+                a.config.skip_reason(&during, false, false)
+            );
+            assert_eq!(None, a.config.skip_reason(&during, true, false));
+            assert!(a
+                .config
+                .skip_reason(&before, true, false)
+                .unwrap()
+                .starts_with("future"));
+            assert_eq!(None, a.config.skip_reason(&before, true, true));
+            assert!(a
+                .config
+                .skip_reason(&after, true, true)
+                .unwrap()
+                .starts_with("expired"));
+        }
+
+        #[test]
+        fn listed_published() {
+            let a: Article = create_article(
+                "---\nlayout: page\ntitle: cats\n---\ncat",
+                "listed_published",
+            )
+            .unwrap();
+            assert!(a.config.listed && a.config.published);
+            assert!(!a.config.visible);
+
+            let a: Article = create_article(
+                "---\nlayout: page\ntitle: cats\ntitlebar: true\n---\ncat",
+                "listed_published",
+            )
+            .unwrap();
+            assert!(a.config.visible && a.config.listed);
+            let config = a.config_liquid.get("config").unwrap().as_object().unwrap();
+            assert_eq!(
+                "true true",
+                format!(
+                    "{} {}",
+                    config.get("visible").unwrap().to_kstr(),
+                    config.get("listed").unwrap().to_kstr()
+                )
+            );
+
+            let a: Article = create_article(
+                "---\nlayout: page\ntitle: cats\nunlisted: true\n---\ncat",
+                "listed_published",
+            )
+            .unwrap();
+            assert!(!a.config.listed);
+            assert_eq!(
+                None,
+                a.config.skip_reason(&chrono::Utc::now(), false, false)
+            );
+
+            let a: Article = create_article(
+                "---\nlayout: page\ntitle: cats\npublished: false\n---\ncat",
+                "listed_published",
+            )
+            .unwrap();
+            assert_eq!(
+                Some("unpublished".to_string()),
+                a.config.skip_reason(&chrono::Utc::now(), true, true)
+            );
+        }
+
+        #[test]
+        fn excerpt_separator() {
+            let a: Article = create_article(
+                "---\nlayout: page\ntitle: cats\n---\ncats\n\nand dogs\n<!--more-->\nand more",
+                "excerpt_separator",
+            )
+            .unwrap();
+            assert_eq!("cats\n\nand dogs", a.excerpt);
+            assert_eq!(
+                "<p>cats</p>\n<p>and dogs</p>\n",
+                a.config_liquid
+                    .get("excerpt")
+                    .unwrap()
+                    .to_kstr()
+                    .to_string()
+            );
+        }
+
+        #[test]
+        fn excerpt_first_paragraph() {
+            let a: Article = create_article(
+                "---\nlayout: page\ntitle: cats\n---\ncats\nand dogs\n\nand more",
+                "excerpt_first_paragraph",
+            )
+            .unwrap();
+            assert_eq!("cats\nand dogs", a.excerpt);
+        }
+
+        #[test]
+        fn excerpt_liquid_blocks() {
+            use crate::article::excerpt;
+            assert_eq!(
+                "{% if true %}\nhello\n\nworld\n{% endif %}",
+                excerpt(
+                    "{% if true %}\nhello\n\nworld\n{% endif %}\n\nmore",
+                    "<!--more-->"
+                )
+            );
+            assert_eq!(
+                "cats {% for i in (1..2) %}{% if i %}\n\n{% endif %}{% endfor %}",
+                excerpt(
+                    "cats {% for i in (1..2) %}{% if i %}\n\n{% endif %}{% endfor %}\n\ndogs",
+                    "<!--more-->"
+                )
+            );
+            assert_eq!(
+                "{% aside %}<!--more-->{% endaside %}\ncats",
+                excerpt(
+                    "{% aside %}<!--more-->{% endaside %}\ncats<!--more-->dogs",
+                    "<!--more-->"
+                )
+            );
+            assert_eq!(
+                "{% assign a = 1 %}",
+                excerpt("{% assign a = 1 %}\n\n{{ a }}", "<!--more-->")
+            );
+        }
+
+        #[test]
+        fn permalink_title() {
+            let a: Article = create_article(
+                "---\nlayout: page\ntitle: \"Cats & Dogs: Part 2!\"\n---\ncat",
+                "permalink_title",
+            )
+            .unwrap();
+            assert_eq!("/cats-dogs-part-2.html", a.url);
+            assert_eq!(
+                std::path::PathBuf::from("_output/cats-dogs-part-2.html"),
+                a.output_path(std::path::Path::new("_output"))
+            );
+        }
+
+        #[test]
+        fn permalink_pattern() {
+            let a: Article = create_article(
+                "---\nlayout: page\ntitle: cats\ncategories: [Pets, Big Cats]\npermalink: /:categories/:year/:month/:day/:title/\n---\ncat",
+                "2020-01-02-permalink-pattern.md",
+            )
+            .unwrap();
+            assert_eq!("/pets/big-cats/2020/01/02/cats/", a.url);
+            assert_eq!(
+                std::path::PathBuf::from("_output/pets/big-cats/2020/01/02/cats/index.html"),
+                a.output_path(std::path::Path::new("_output"))
+            );
+
+            let a: Article = create_article(
+                "---\nlayout: page\ntitle: cats\npermalink: /:categories/:slug.html\n---\ncat",
+                "permalink-no-categories.md",
+            )
+            .unwrap();
+            assert_eq!("/permalink-no-categories.html", a.url);
+        }
+
+        #[test]
+        fn permalink_site() {
+            let mut site = SiteConfig::default();
+            site.permalink = Some("/:year/:slug/".to_string());
+
+            let a: Article = create_article_with(
+                "---\nlayout: page\ntitle: cats\n---\ncat",
+                "2020-01-02-permalink-site.md",
+                &site,
+            )
+            .unwrap();
+            assert_eq!("/2020/permalink-site/", a.url);
+
+            // no date so the site permalink doesn't apply
+            let a: Article = create_article_with(
+                "---\nlayout: page\ntitle: About Me\n---\ncat",
+                "about.md",
+                &site,
+            )
+            .unwrap();
+            assert_eq!("/about-me.html", a.url);
+        }
+
+        #[test]
+        fn permalink_pretty() {
+            let mut site = SiteConfig::default();
+            site.pretty_urls = true;
+
+            let a: Article = create_article_with(
+                "---\nlayout: page\ntitle: About Me\n---\ncat",
+                "permalink_pretty",
+                &site,
+            )
+            .unwrap();
+            assert_eq!("/about-me/", a.url);
+            assert_eq!(
+                std::path::PathBuf::from("_output/about-me/index.html"),
+                a.output_path(std::path::Path::new("_output"))
+            );
+        }
+
+        #[test]
+        fn permalink_page() {
+            let mut site = SiteConfig::default();
+            site.pretty_urls = true;
+
+            let a: Article =
+                create_article_with("---\n---\n# cat", "permalink_page.xml", &site).unwrap();
+            assert_eq!("/permalink_page.xml", a.url);
+            assert_eq!(Some("xml".to_string()), a.config.page_extension);
+            assert!(!a.config.listed);
+            assert_eq!(
+                "# cat",
+                a.config_liquid
+                    .get("excerpt")
+                    .unwrap()
+                    .to_kstr()
+                    .to_string()
+            );
+
+            let a: Article = create_article_with(
+                "---\npermalink: /feed/atom.xml\n---\ncat",
+                "permalink_page_set.xml",
+                &site,
+            )
+            .unwrap();
+            assert_eq!("/feed/atom.xml", a.url);
+            assert!(create_article("---\n---\ncat", "permalink_page_md").is_err());
+        }
+
+        #[test]
+        fn permalink_empty_slug() {
+            let a: Article = create_article(
+                "---\nlayout: page\ntitle: 猫\n---\ncat",
+                "permalink_empty_slug",
+            )
+            .unwrap();
+            assert_eq!("/permalink-empty-slug.html", a.url);
+            match create_article("---\nlayout: page\ntitle: 猫\n---\ncat", "猫") {
+                Err(ParseError::InvalidConfig(e)) => assert!(e.contains(":title"), "{}", e),
+                e => assert!(false, "looking for ParseError::InvalidConfig found {:?}", e),
+            }
+        }
+
+        #[test]
+        fn permalink_missing_date() {
+            assert!(create_article(
+                "---\nlayout: page\ntitle: cats\npermalink: /:year/:title/\n---\ncat",
+                "permalink_missing_date",
+            )
+            .is_err());
+        }
+
+        #[test]
+        fn markdown_unknown_extension() {
+            match create_article(
+                "---\nlayout: page\ntitle: cats\nmarkdown: { smart: true }\n---\ncat",
+                "markdown_unknown_extension",
+            ) {
+                Err(ParseError::InvalidValue(e)) => assert!(e.contains("smart"), "{}", e),
+                e => assert!(false, "looking for ParseError::InvalidValue found {:?}", e),
+            }
+        }
+
+        #[test]
+        fn unknown_key_line() {
+            let e = create_article_strict(
+                "---\nlayout: page\ntitle: cats\ncats: dogs\n---\ncat",
+                "unknown_key_line",
+            )
+            .err();
+            match e {
+                Some(ParseError::InvalidKey(config)) => {
+                    assert!(
+                        config.contains("unknown_key_line 4:1"),
+                        "expected 'unknown_key_line 4:1' in {}",
+                        config
+                    )
+                }
+                _ => assert!(false, "looking for ParseError::InvalidKey found {:?}", e),
+            }
+        }
+
         #[test]
         fn more_than_three_dashes() {
             let e = create_article(
@@ -525,6 +1465,178 @@ mod render {
             );
         }
 
+        #[test]
+        fn render_site() {
+            assert_eq!(
+                "mole cats and dogs".to_string(),
+                gen_render_mocks(
+                    "---\r\nlayout: page\r\ntitle:cats and dogs\n---\r\ncat",
+                    "render_site",
+                    vec![(
+                        "default".to_string(),
+                        "{{site.title}} {{page.config.title}}".to_string()
+                    )],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn render_excerpt() {
+            assert_eq!(
+                "<p>cat <em>mole</em></p>\n".to_string(),
+                gen_render_mocks(
+                    "---\r\nlayout: page\r\ntitle:cats and dogs\n---\r\ncat *{{site.title}}*\n\ndog",
+                    "render_excerpt",
+                    vec![("default".to_string(), "{{page.excerpt}}".to_string())],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+            assert_eq!(
+                "<p>hello</p>\n<p>world</p>\n".to_string(),
+                gen_render_mocks(
+                    "---\nlayout: page\ntitle: cats\n---\n{% if true %}\nhello\n\nworld\n{% endif %}\n\nmore",
+                    "render_excerpt_block",
+                    vec![("default".to_string(), "{{page.excerpt}}".to_string())],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn render_gfm() {
+            assert_eq!(
+                "<table><thead><tr><th>a</th></tr></thead><tbody>\n<tr><td><del>b</del></td></tr>\n</tbody></table>\n".to_string(),
+                gen_render_mocks(
+                    "---\nlayout: page\ntitle: cats\n---\n| a |\n|---|\n| ~~b~~ |",
+                    "render_gfm",
+                    vec![("default".to_string(), "{{page.content}}".to_string())],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn render_gfm_off() {
+            assert_eq!(
+                "<p>~~b~~</p>\n".to_string(),
+                gen_render_mocks(
+                    "---\nlayout: page\ntitle: cats\nmarkdown:\n  strikethrough: false\n---\n~~b~~",
+                    "render_gfm_off",
+                    vec![("default".to_string(), "{{page.content}}".to_string())],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn render_smart_punctuation() {
+            assert_eq!(
+                "<p>“cats” – ‘dogs’ <code>'mice'</code></p>\n".to_string(),
+                gen_render_mocks(
+                    "---\nlayout: page\ntitle: cats\nmarkdown:\n  smart_punctuation: true\n---\n\"cats\" -- 'dogs' `'mice'`",
+                    "render_smart_punctuation",
+                    vec![("default".to_string(), "{{page.content}}".to_string())],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+            assert_eq!(
+                "<p>&quot;cats&quot; -- 'dogs'</p>\n".to_string(),
+                gen_render_mocks(
+                    "---\nlayout: page\ntitle: cats\n---\n\"cats\" -- 'dogs'",
+                    "render_smart_punctuation_off",
+                    vec![("default".to_string(), "{{page.content}}".to_string())],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn render_admonitions() {
+            assert_eq!(
+                "<div class=\"callout callout-tip\">\n<p class=\"callout-title\">Tip</p>\n<p>cats</p>\n</div>\n".to_string(),
+                gen_render_mocks(
+                    "---\nlayout: page\ntitle: cats\n---\n> [!TIP]\n> cats",
+                    "render_admonitions",
+                    vec![("default".to_string(), "{{page.content}}".to_string())],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+            assert_eq!(
+                "<blockquote>\n<p>[!TIP]\ncats</p>\n</blockquote>\n".to_string(),
+                gen_render_mocks(
+                    "---\nlayout: page\ntitle: cats\nmarkdown:\n  admonitions: false\n---\n> [!TIP]\n> cats",
+                    "render_admonitions_off",
+                    vec![("default".to_string(), "{{page.content}}".to_string())],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn render_toc() {
+            let layout = "{% for h in page.toc %}{{h.id}} {{h.children[0].text}}|{% endfor %}{{page.toc_html}}{{page.content}}";
+            assert_eq!(
+                "cats Dogs|<nav class=\"toc\"><ul><li><a href=\"#cats\">Cats</a><ul><li><a href=\"#dogs\">Dogs</a></li></ul></li></ul></nav>\n<h1 id=\"cats\">Cats</h1>\n<h2 id=\"dogs\">Dogs</h2>\n".to_string(),
+                gen_render_mocks(
+                    "---\nlayout: page\ntitle: cats\n---\n# Cats\n## Dogs",
+                    "render_toc",
+                    vec![("default".to_string(), layout.to_string())],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+            assert_eq!(
+                "<h1 id=\"cats\">Cats</h1>\n".to_string(),
+                gen_render_mocks(
+                    "---\nlayout: page\ntitle: cats\ntoc: false\n---\n# Cats",
+                    "render_toc_off",
+                    vec![("default".to_string(), layout.to_string())],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
+        #[test]
+        fn render_page() {
+            assert_eq!(
+                "<title>mole</title>\n*cat* -- \"dog\"".to_string(),
+                gen_render_mocks(
+                    "---\n---\n<title>{{site.title}}</title>\n*cat* -- \"dog\"",
+                    "render_page.xml",
+                    vec![("default".to_string(), "layout".to_string())],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+            assert_eq!(
+                "<main># cats</main>".to_string(),
+                gen_render_mocks(
+                    "---\nlayout: page\n---\n# {{page.config.title | default: \"cats\"}}",
+                    "render_page_layout.html",
+                    vec![
+                        (
+                            "default".to_string(),
+                            "<main>{% include layout %}</main>".to_string()
+                        ),
+                        ("page".to_string(), "{{page.content}}".to_string())
+                    ],
+                    &liquid::object!({})
+                )
+                .unwrap()
+            );
+        }
+
         #[test]
         fn render_chained_includes() {
             assert_eq!(
@@ -25,6 +25,7 @@ use tiny_http::{Request, Response, Server};
 pub enum SubCommands {
     INIT(InitCommand),
     BUILD(BuildCommand),
+    HIGHLIGHT(HighlightCssCommand),
     // CLEAN(CleanCommand),
     // NEW(NewCommand),
     // SERVE(ServeCommand)
@@ -35,6 +36,7 @@ impl SubCommands {
         match self {
             SubCommands::INIT(x) => x.run(),
             SubCommands::BUILD(x) => x.run(),
+            SubCommands::HIGHLIGHT(x) => x.run(),
         }
     }
 }
@@ -68,7 +70,8 @@ impl InitCommand {
             - _sources (although what is this actually meant to be for)
             - _scss
             - _output
-            - .mole.toml -> going to be used to identify the project (just so that clean is safer)
+            - mole.toml -> going to be used to identify the project (just so that clean is safer),
+              `mole build` already reads it see `mole::site::SiteConfig`
         */
         } else {
             error!("{:?} is not a directory so could not initailize", current);
@@ -77,7 +80,11 @@ impl InitCommand {
 }
 
 // largely copied from cobalt-org/cobalt.rs/src/bin/serve.rs as it's under MIT
-fn static_file_handler(dest: &Path, req: Request) -> Result<(), mole::error::CustomError> {
+fn static_file_handler(
+    dest: &Path,
+    baseurl: &str,
+    req: Request,
+) -> Result<(), mole::error::CustomError> {
     // grab the requested path
     let mut req_path = req.url().to_string();
 
@@ -87,6 +94,21 @@ fn static_file_handler(dest: &Path, req: Request) -> Result<(), mole::error::Cus
         req_path.truncate(position);
     }
 
+    // "/" goes to the baseurl and anything outside of it doesn't exist
+    if !baseurl.is_empty() && req_path == "/" {
+        req.respond(Response::empty(302).with_header(
+            tiny_http::Header::from_bytes(&b"Location"[..], format!("{}/", baseurl)).unwrap(),
+        ))?;
+        return Ok(());
+    }
+    let inside_baseurl = match req_path.strip_prefix(baseurl).map(|rest| rest.to_string()) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            req_path = format!("/{}", rest.trim_start_matches('/'));
+            true
+        }
+        _ => false,
+    };
+
     // find the path of the file in the local system
     // (this gets rid of the '/' in `p`, so the `join()` will not replace the path)
     let path = dest.to_path_buf().join(Path::new(&req_path[1..]));
@@ -100,7 +122,7 @@ fn static_file_handler(dest: &Path, req: Request) -> Result<(), mole::error::Cus
         path.join("index.html")
     };
 
-    if serve_path.exists() {
+    if inside_baseurl && serve_path.exists() {
         let file = fs::File::open(&serve_path)?;
         let content_type =
             if let Some(mime) = mime_guess::MimeGuess::from_path(&serve_path).first_raw() {
@@ -129,16 +151,17 @@ fn static_file_handler(dest: &Path, req: Request) -> Result<(), mole::error::Cus
 }
 
 // largely copied from cobalt-org/cobalt.rs/src/bin/serve.rs as it's under MIT
-fn serve(dest: &Path, ip: &str) -> Result<(), mole::error::CustomError> {
+/// the site is served under `baseurl` so links made with `relative_url` work the same as when deployed
+fn serve(dest: &Path, baseurl: &str, ip: &str) -> Result<(), mole::error::CustomError> {
     info!("Serving {:?} through static file server", dest);
-    info!("Server Listening on http://{}", &ip);
+    info!("Server Listening on http://{}{}/", &ip, baseurl);
     info!("Ctrl-c to stop the server");
 
     // attempts to create a server
     let server = Server::http(ip).map_err(|e| mole::error::CustomError::IOError(e.to_string()))?;
 
     for request in server.incoming_requests() {
-        if let Err(e) = static_file_handler(&dest, request) {
+        if let Err(e) = static_file_handler(&dest, baseurl, request) {
             error!("{:?}", e);
         }
     }
@@ -156,29 +179,33 @@ pub struct BuildCommand {
     /// path to output too
     current: String,
 
-    #[argh(option, default = "PathBuf::from(\"_output/\")")]
-    /// path to output too
-    dest: PathBuf,
+    #[argh(option)]
+    /// path to output too (default: _output/)
+    dest: Option<PathBuf>,
+
+    #[argh(option)]
+    /// path from 'source' to include folder (default: _include/)
+    include: Option<PathBuf>,
 
-    #[argh(option, default = "PathBuf::from(\"_include/\")")]
-    /// path from 'source' to include folder
-    include: PathBuf,
+    #[argh(option)]
+    /// path from 'source' to layouts folder (default: _layouts/)
+    layouts: Option<PathBuf>,
 
-    #[argh(option, default = "PathBuf::from(\"_layouts/\")")]
-    /// path from 'source' to layouts folder
-    layouts: PathBuf,
+    #[argh(option)]
+    /// path from 'source' to shortcodes folder (default: _shortcodes/)
+    shortcodes: Option<PathBuf>,
 
-    #[argh(option, default = "PathBuf::from(\"_articles/\")")]
-    /// path from 'source' to articles folder
-    articles: PathBuf,
+    #[argh(option)]
+    /// path from 'source' to articles folder (default: _articles/)
+    articles: Option<PathBuf>,
 
-    #[argh(option, default = "PathBuf::from(\"_css/\")")]
-    /// path from 'source' to articles folder
-    scss: PathBuf,
+    #[argh(option)]
+    /// path from 'source' to articles folder (default: _css/)
+    scss: Option<PathBuf>,
 
-    #[argh(option, default = "PathBuf::from(\"_css/\")")]
-    /// path from 'source' to articles folder
-    scss_load_paths: PathBuf,
+    #[argh(option)]
+    /// path from 'source' to articles folder (default: _css/)
+    scss_load_paths: Option<PathBuf>,
 
     #[argh(switch)]
     /// whether or not to check the project for changes and if changed rebuild
@@ -195,39 +222,159 @@ pub struct BuildCommand {
     #[argh(switch)]
     /// shows full backtrace with file locations for liquid errors
     backtrace: bool,
+
+    #[argh(switch)]
+    /// fail on article config keys that mole doesn't know about
+    strict: bool,
+
+    #[argh(switch)]
+    /// include articles with `draft: true` (on by default with --serve)
+    drafts: bool,
+
+    #[argh(switch)]
+    /// include articles with a date in the future (on by default with --serve)
+    future: bool,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(
+    subcommand,
+    name = "highlight-css",
+    description = "writes highlight.css for the [highlight] theme, used with mode = \"classes\""
+)]
+pub struct HighlightCssCommand {
+    #[argh(positional, default = "String::from(\"./\")")]
+    /// path to the project
+    current: String,
+
+    #[argh(option)]
+    /// path to output too (default: _output/)
+    dest: Option<PathBuf>,
+}
+
+impl HighlightCssCommand {
+    pub fn run(self) {
+        let current = Path::new(&self.current);
+        let site = match mole::site::SiteConfig::load(current) {
+            Ok(site) => site,
+            Err(e) => {
+                error!("{}", e);
+                process::exit(1);
+            }
+        };
+        let dest = resolve(current, &self.dest, &site.build.dest, "_output/");
+
+        let css = mole::highlight::Highlighter::new(&site.highlight).and_then(|h| h.css());
+        match css {
+            Ok(css) => {
+                let path = dest.join("highlight.css");
+                info!("writing {:?} theme to {:?}", site.highlight.theme, path);
+                if let Err(e) = fs::create_dir_all(&dest).and_then(|_| fs::write(&path, css)) {
+                    error!("could not write {:?} {}", path, e);
+                    process::exit(1);
+                }
+            }
+            Err(e) => {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// flag > mole.toml > default, all relative to the project directory
+fn resolve(
+    current: &Path,
+    flag: &Option<PathBuf>,
+    config: &Option<PathBuf>,
+    default: &str,
+) -> PathBuf {
+    current.join(
+        flag.as_ref()
+            .or(config.as_ref())
+            .cloned()
+            .unwrap_or_else(|| PathBuf::from(default)),
+    )
 }
 
 impl BuildCommand {
-    pub fn run(mut self) {
+    /// (re)loads mole.toml and builds the site, returns the output directory and baseurl
+    /// or None if the config file couldn't be read or the build failed
+    fn build(&self, current: &Path) -> Option<(PathBuf, String)> {
+        let mut site = match mole::site::SiteConfig::load(current) {
+            Ok(site) => site,
+            Err(e) => {
+                error!("{}", e);
+                return None;
+            }
+        };
+        if self.strict {
+            site.build.strict = Some(true);
+        }
+        let config = &site.build;
+
+        let dest = resolve(current, &self.dest, &config.dest, "_output/");
+        let include = resolve(current, &self.include, &config.include, "_include/");
+        let layouts = resolve(current, &self.layouts, &config.layouts, "_layouts/");
+        let shortcodes = resolve(
+            current,
+            &self.shortcodes,
+            &config.shortcodes,
+            "_shortcodes/",
+        );
+        let articles = resolve(current, &self.articles, &config.articles, "_articles/");
+        let scss = resolve(current, &self.scss, &config.scss, "_css/");
+        let scss_load_paths = resolve(
+            current,
+            &self.scss_load_paths,
+            &config.scss_load_paths,
+            "_css/",
+        );
+
+        let root = PathBuf::from(current);
+        let article_dirs = vec![&articles, &root];
+
+        let build = mole::Build::new(&dest, self.backtrace)
+            .site(&site)
+            .drafts(self.drafts || self.serve)
+            .future(self.future || self.serve)
+            .includes(&include, false)
+            .includes(&layouts, true)
+            .shortcodes(&shortcodes)
+            .articles(&article_dirs)
+            .collections()
+            .sass(&scss, &vec![&scss_load_paths]);
+
+        if let Err(e) = build.run() {
+            error!("{}", e);
+            return None;
+        }
+
+        Some((dest, site.baseurl))
+    }
+
+    pub fn run(self) {
         if self.version {
             info!("version: {:?}", env!("CARGO_PKG_VERSION"));
         }
         let current = Path::new(&self.current);
-        self.dest = current.join(self.dest);
-        self.include = current.join(self.include);
-        self.layouts = current.join(self.layouts);
-        self.articles = current.join(self.articles);
-        self.scss = current.join(self.scss);
         if current.is_dir() {
             info!("building");
-            mole::Build::new(&self.dest, self.backtrace)
-                .includes(&self.include, false)
-                .includes(&self.layouts, true)
-                .articles(&vec![&self.articles, &PathBuf::from(current)])
-                .sass(&self.scss, &vec![&self.scss_load_paths])
-                .run();
+            let (dest, baseurl) = match self.build(current) {
+                Some(build) => build,
+                None => process::exit(1),
+            };
 
             if self.serve {
-                let dest = Path::new("").join(&self.dest);
                 if self.watch {
                     thread::spawn(move || {
-                        if let Err(e) = serve(&dest, "127.0.0.1:4000") {
+                        if let Err(e) = serve(&dest, &baseurl, "127.0.0.1:4000") {
                             error!("{:?}", e);
                         }
                         process::exit(1);
                     });
                 } else {
-                    if let Err(e) = serve(&dest, "127.0.0.1:4000") {
+                    if let Err(e) = serve(&dest, &baseurl, "127.0.0.1:4000") {
                         error!("{:?}", e);
                     }
                 }
@@ -250,12 +397,7 @@ impl BuildCommand {
                             Ok(event) => {
                                 info!("{:?}", event);
                                 info!("re-building");
-                                mole::Build::new(&self.dest, self.backtrace)
-                                    .includes(&self.include, false)
-                                    .includes(&self.layouts, true)
-                                    .articles(&vec![&self.articles, &PathBuf::from(current)])
-                                    .sass(&self.scss, &vec![&self.scss_load_paths])
-                                    .run();
+                                self.build(current);
                             }
                             Err(e) => error!("watch error: {:?}", e),
                         }
@@ -0,0 +1,144 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+/// timezone for dates that don't say which one they are in, set with `timezone` in mole.toml
+/// either as a name e.g. "Europe/London" or as an offset e.g. "+01:00"
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timezone {
+    Offset(FixedOffset),
+    Named(Tz),
+}
+
+impl Default for Timezone {
+    fn default() -> Self {
+        Timezone::Offset(FixedOffset::east(0))
+    }
+}
+
+impl FromStr for Timezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s == "UTC" || s == "Z" {
+            return Ok(Timezone::default());
+        }
+        if let Some(offset) = parse_offset(s) {
+            return Ok(Timezone::Offset(offset));
+        }
+        s.parse::<Tz>()
+            .map(Timezone::Named)
+            .map_err(|_| format!("unknown timezone {:?} expected a name like \"Europe/London\" or an offset like \"+01:00\"", s))
+    }
+}
+
+impl Timezone {
+    /// None when the time doesn't exist in the timezone e.g. skipped over by daylight saving,
+    /// times that happen twice use the first one
+    pub fn localise(&self, date: &NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+        match self {
+            Timezone::Offset(offset) => offset.from_local_datetime(date).single(),
+            Timezone::Named(tz) => tz
+                .from_local_datetime(date)
+                .earliest()
+                .map(|d| d.with_timezone(&d.offset().fix())),
+        }
+    }
+}
+
+/// "+01:00", "+0100" or "-05"
+fn parse_offset(s: &str) -> Option<FixedOffset> {
+    let sign = match s.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let rest = s[1..].replace(':', "");
+    if !rest.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match rest.len() {
+        2 => (rest.parse::<i32>().ok()?, 0),
+        4 => (
+            rest[..2].parse::<i32>().ok()?,
+            rest[2..].parse::<i32>().ok()?,
+        ),
+        _ => return None,
+    };
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// dates with an offset (rfc 3339 or `2020-01-02 10:30:00 +01:00`) are kept as they are,
+/// anything without one is taken to be in `timezone`
+pub fn parse_date(s: &str, timezone: &Timezone) -> Result<DateTime<FixedOffset>, String> {
+    let s = s.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+        return Ok(date);
+    }
+    for format in &[
+        "%Y-%m-%d %H:%M:%S %z",
+        "%Y-%m-%d %H:%M:%S%.f %z",
+        "%Y-%m-%d %H:%M %z",
+        "%Y-%m-%dT%H:%M:%S%.f%z",
+    ] {
+        if let Ok(date) = DateTime::parse_from_str(s, format) {
+            return Ok(date);
+        }
+    }
+
+    let naive = if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        date.and_hms(0, 0, 0)
+    } else {
+        [
+            "%Y-%m-%d %H:%M:%S",
+            "%Y-%m-%d %H:%M:%S%.f",
+            "%Y-%m-%dT%H:%M:%S",
+            "%Y-%m-%dT%H:%M:%S%.f",
+            "%Y-%m-%d %H:%M",
+        ]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .ok_or_else(|| {
+            "expected Y-m-d, Y-m-d H:M, Y-m-d H:M:S or rfc 3339 e.g. 2020-01-02T10:30:00+01:00"
+                .to_string()
+        })?
+    };
+
+    timezone
+        .localise(&naive)
+        .ok_or_else(|| format!("{} doesn't exist in {:?}", naive, timezone))
+}
+
+#[cfg(test)]
+mod date_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse_naive_uses_timezone() {
+        let date = parse_date("2020-07-01 10:30", &"Europe/London".parse().unwrap()).unwrap();
+        assert_eq!("2020-07-01T10:30:00+01:00", date.to_rfc3339());
+
+        let date = parse_date("2020-01-01", &"+05:30".parse().unwrap()).unwrap();
+        assert_eq!("2020-01-01T00:00:00+05:30", date.to_rfc3339());
+    }
+
+    #[test]
+    fn parse_with_offset() {
+        let timezone = "Europe/London".parse().unwrap();
+        let date = parse_date("2020-07-01T10:30:15-05:00", &timezone).unwrap();
+        assert_eq!("2020-07-01T10:30:15-05:00", date.to_rfc3339());
+
+        let date = parse_date("2020-07-01 10:30:15 +0200", &timezone).unwrap();
+        assert_eq!("2020-07-01T10:30:15+02:00", date.to_rfc3339());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse_date("01/07/2020", &Timezone::default()).is_err());
+        assert!("Mars/Olympus_Mons".parse::<Timezone>().is_err());
+        // skipped over by daylight saving
+        assert!(parse_date("2020-03-29 01:30", &"Europe/London".parse().unwrap()).is_err());
+    }
+}
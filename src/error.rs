@@ -4,6 +4,8 @@ use std::{fmt::Display, path::PathBuf};
 pub enum CustomError {
     IOError(String),
     LiquidError(String),
+    ConfigError(String),
+    OutputError(String),
 }
 
 impl Display for CustomError {
@@ -11,6 +13,8 @@ impl Display for CustomError {
         match self {
             CustomError::IOError(s) => write!(f, "IO error: {}\n", s),
             CustomError::LiquidError(s) => write!(f, "Liquid error: {}\n", s),
+            CustomError::ConfigError(s) => writeln!(f, "Config error: {}", s),
+            CustomError::OutputError(s) => writeln!(f, "Output error: {}", s),
         }
     }
 }
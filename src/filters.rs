@@ -0,0 +1,750 @@
+//! liquid filters mole adds on top of the standard library
+use crate::article::markdown;
+use crate::date::{parse_date, Timezone};
+use crate::site::{MarkdownConfig, SiteConfig};
+use crate::util;
+use chrono::{DateTime, FixedOffset};
+use liquid_core::parser::{FilterArguments, ParameterReflection};
+use liquid_core::runtime::{Expression, Runtime};
+use liquid_core::{Error, Filter, FilterReflection, ParseFilter, Result, Value, ValueView};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// adds mole's filters, used for both the build and the article tests
+pub fn register<P: liquid::partials::PartialCompiler>(
+    builder: liquid::ParserBuilder<P>,
+    site: &SiteConfig,
+) -> liquid::ParserBuilder<P> {
+    builder
+        .filter(RelativeUrl {
+            baseurl: site.baseurl.clone(),
+        })
+        .filter(AbsoluteUrl {
+            url: site.url.clone(),
+            baseurl: site.baseurl.clone(),
+        })
+        .filter(DateToXmlSchema {
+            timezone: site.timezone,
+        })
+        .filter(Where)
+        .filter(SortBy {
+            timezone: site.timezone,
+        })
+        .filter(GroupBy {
+            timezone: site.timezone,
+        })
+        .filter(Slugify)
+        .filter(Markdownify {
+            markdown: site.markdown.clone(),
+        })
+}
+
+/// `FilterReflection` for the type liquid parses with and `Display` for the filter it makes,
+/// they're the same type for filters without arguments
+macro_rules! reflection {
+    ($parser:ty => $filter:ty, $name:literal, $description:literal) => {
+        impl fmt::Display for $filter {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, $name)
+            }
+        }
+
+        impl FilterReflection for $parser {
+            fn name(&self) -> &str {
+                $name
+            }
+
+            fn description(&self) -> &str {
+                $description
+            }
+
+            fn positional_parameters(&self) -> &'static [ParameterReflection] {
+                &[]
+            }
+
+            fn keyword_parameters(&self) -> &'static [ParameterReflection] {
+                &[]
+            }
+        }
+    };
+}
+
+/// the positional arguments, erroring if there are keyword ones or the wrong number
+fn positional_arguments(
+    name: &str,
+    arguments: FilterArguments,
+    required: usize,
+    optional: usize,
+) -> Result<Vec<Expression>> {
+    if arguments.keyword.count() > 0 {
+        return Err(Error::with_msg(format!(
+            "{} doesn't take keyword arguments",
+            name
+        )));
+    }
+    let positional: Vec<Expression> = arguments.positional.collect();
+    if positional.len() < required || positional.len() > required + optional {
+        return Err(Error::with_msg(format!(
+            "{} expects {} arguments, found {}",
+            name,
+            if optional == 0 {
+                required.to_string()
+            } else {
+                format!("{} to {}", required, required + optional)
+            },
+            positional.len()
+        )));
+    }
+    Ok(positional)
+}
+
+fn to_string(value: &dyn ValueView) -> String {
+    value.to_kstr().to_string()
+}
+
+/// `property` can be a path into nested objects, "config.date" on a `global.articles` item
+pub(crate) fn property<'v>(value: &'v dyn ValueView, path: &str) -> Option<&'v dyn ValueView> {
+    path.split('.')
+        .try_fold(value, |value, key| value.as_object()?.get(key))
+}
+
+/// nil is treated as an empty list so filters can be chained off a missing variable
+fn items<'v>(name: &str, input: &'v dyn ValueView) -> Result<Vec<&'v dyn ValueView>> {
+    if input.is_nil() {
+        return Ok(Vec::new());
+    }
+    match input.as_array() {
+        Some(array) => Ok(array.values().collect()),
+        None => Err(Error::with_msg(format!(
+            "{} expects an array, found {}",
+            name,
+            input.type_name()
+        ))),
+    }
+}
+
+/// what an item sorts by, every item in one sort gets the same kind
+#[derive(PartialEq, PartialOrd)]
+enum SortKey {
+    Date(DateTime<FixedOffset>),
+    Number(f64),
+    Text(String),
+}
+
+/// the keys if every value that's there can be turned into one
+fn sort_keys(
+    values: &[Option<&dyn ValueView>],
+    key: impl Fn(&dyn ValueView) -> Option<SortKey>,
+) -> Option<Vec<Option<SortKey>>> {
+    values
+        .iter()
+        .map(|value| match value {
+            Some(value) => key(*value).map(Some),
+            None => Some(None),
+        })
+        .collect()
+}
+
+/// sorts `items` by the property at `path`, stable so equal items stay in the order they came
+/// in. If every value is a date they compare as dates, otherwise if every value is a number as
+/// numbers, otherwise all of them as text. Missing values go last
+pub(crate) fn sort_by_property<'v, T: Copy>(
+    items: &mut Vec<T>,
+    path: &str,
+    timezone: &Timezone,
+    view: impl Fn(T) -> &'v dyn ValueView,
+) {
+    let values: Vec<Option<&dyn ValueView>> = items
+        .iter()
+        .map(|item| property(view(*item), path).filter(|value| !value.is_nil()))
+        .collect();
+    let keys = sort_keys(&values, |value| {
+        parse_date(&to_string(value), timezone)
+            .ok()
+            .map(SortKey::Date)
+    })
+    .or_else(|| {
+        sort_keys(&values, |value| {
+            value.as_scalar()?.to_float().map(SortKey::Number)
+        })
+    })
+    .or_else(|| sort_keys(&values, |value| Some(SortKey::Text(to_string(value)))))
+    .unwrap();
+
+    let mut keyed: Vec<(Option<SortKey>, T)> = keys.into_iter().zip(items.drain(..)).collect();
+    keyed.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Some(a), Some(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+    items.extend(keyed.into_iter().map(|(_, item)| item));
+}
+
+fn is_absolute(url: &str) -> bool {
+    url.contains("://") || url.starts_with("//")
+}
+
+/// `baseurl` is either empty or "/sub/path" without a trailing '/', see `SiteConfig::parse`
+pub fn relative_url(baseurl: &str, input: &str) -> String {
+    if is_absolute(input) {
+        input.to_string()
+    } else {
+        format!("{}/{}", baseurl, input.trim_start_matches('/'))
+    }
+}
+
+pub fn absolute_url(url: &str, baseurl: &str, input: &str) -> String {
+    if is_absolute(input) {
+        input.to_string()
+    } else {
+        format!("{}{}", url, relative_url(baseurl, input))
+    }
+}
+
+/// `{{ page.url | relative_url }}` puts `baseurl` in front of the url
+#[derive(Debug, Clone)]
+struct RelativeUrl {
+    baseurl: String,
+}
+
+impl fmt::Display for RelativeUrl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "relative_url")
+    }
+}
+
+impl FilterReflection for RelativeUrl {
+    fn name(&self) -> &str {
+        "relative_url"
+    }
+
+    fn description(&self) -> &str {
+        "prefixes the url with the site's baseurl"
+    }
+
+    fn positional_parameters(&self) -> &'static [ParameterReflection] {
+        &[]
+    }
+
+    fn keyword_parameters(&self) -> &'static [ParameterReflection] {
+        &[]
+    }
+}
+
+impl ParseFilter for RelativeUrl {
+    fn parse(&self, _arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self
+    }
+}
+
+impl Filter for RelativeUrl {
+    fn evaluate(&self, input: &dyn ValueView, _runtime: &Runtime) -> Result<Value> {
+        Ok(Value::scalar(relative_url(&self.baseurl, &input.to_kstr())))
+    }
+}
+
+/// `{{ page.url | absolute_url }}` puts `url` and `baseurl` in front of the url
+#[derive(Debug, Clone)]
+struct AbsoluteUrl {
+    url: String,
+    baseurl: String,
+}
+
+impl fmt::Display for AbsoluteUrl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "absolute_url")
+    }
+}
+
+impl FilterReflection for AbsoluteUrl {
+    fn name(&self) -> &str {
+        "absolute_url"
+    }
+
+    fn description(&self) -> &str {
+        "prefixes the url with the site's url and baseurl"
+    }
+
+    fn positional_parameters(&self) -> &'static [ParameterReflection] {
+        &[]
+    }
+
+    fn keyword_parameters(&self) -> &'static [ParameterReflection] {
+        &[]
+    }
+}
+
+impl ParseFilter for AbsoluteUrl {
+    fn parse(&self, _arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self
+    }
+}
+
+impl Filter for AbsoluteUrl {
+    fn evaluate(&self, input: &dyn ValueView, _runtime: &Runtime) -> Result<Value> {
+        Ok(Value::scalar(absolute_url(
+            &self.url,
+            &self.baseurl,
+            &input.to_kstr(),
+        )))
+    }
+}
+
+/// `{{ page.config.date | date_to_xml_schema }}` is 2020-01-02T10:30:00+01:00, for feeds and
+/// sitemaps
+#[derive(Debug, Clone)]
+struct DateToXmlSchema {
+    timezone: Timezone,
+}
+
+reflection!(DateToXmlSchema => DateToXmlSchema, "date_to_xml_schema", "formats a date as rfc 3339");
+
+impl ParseFilter for DateToXmlSchema {
+    fn parse(&self, _arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self
+    }
+}
+
+impl Filter for DateToXmlSchema {
+    fn evaluate(&self, input: &dyn ValueView, _runtime: &Runtime) -> Result<Value> {
+        let input = to_string(input);
+        match parse_date(&input, &self.timezone) {
+            Ok(date) => Ok(Value::scalar(date.to_rfc3339())),
+            Err(e) => Err(Error::with_msg(format!(
+                "date_to_xml_schema {:?} isn't a date, {}",
+                input, e
+            ))),
+        }
+    }
+}
+
+/// `{{ global.articles | where: "config.tags", "rust" }}` keeps the items where the property is
+/// the value, or is a list containing it
+#[derive(Debug, Clone)]
+struct Where;
+
+#[derive(Debug)]
+struct WhereFilter {
+    property: Expression,
+    value: Expression,
+}
+
+reflection!(Where => WhereFilter, "where", "keeps the items with a property equal to the value");
+
+impl ParseFilter for Where {
+    fn parse(&self, arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        let mut arguments = positional_arguments("where", arguments, 2, 0)?.into_iter();
+        Ok(Box::new(WhereFilter {
+            property: arguments.next().unwrap(),
+            value: arguments.next().unwrap(),
+        }))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self
+    }
+}
+
+impl Filter for WhereFilter {
+    fn evaluate(&self, input: &dyn ValueView, runtime: &Runtime) -> Result<Value> {
+        let key = self.property.evaluate(runtime)?.to_kstr().to_string();
+        let wanted = self.value.evaluate(runtime)?.to_kstr().to_string();
+
+        let matches = |item: &dyn ValueView| match property(item, &key) {
+            Some(value) => match value.as_array() {
+                Some(values) => values.values().any(|value| to_string(value) == wanted),
+                None => !value.is_nil() && to_string(value) == wanted,
+            },
+            None => false,
+        };
+        Ok(Value::Array(
+            items("where", input)?
+                .into_iter()
+                .filter(|item| matches(*item))
+                .map(|item| item.to_value())
+                .collect(),
+        ))
+    }
+}
+
+/// `{{ global.articles | sort_by: "config.date" | reverse }}` for newest first, items without
+/// the property go at the end
+#[derive(Debug, Clone)]
+struct SortBy {
+    timezone: Timezone,
+}
+
+#[derive(Debug)]
+struct SortByFilter {
+    property: Expression,
+    timezone: Timezone,
+}
+
+reflection!(SortBy => SortByFilter, "sort_by", "sorts the items by a property");
+
+impl ParseFilter for SortBy {
+    fn parse(&self, arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        let mut arguments = positional_arguments("sort_by", arguments, 1, 0)?.into_iter();
+        Ok(Box::new(SortByFilter {
+            property: arguments.next().unwrap(),
+            timezone: self.timezone,
+        }))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self
+    }
+}
+
+impl Filter for SortByFilter {
+    fn evaluate(&self, input: &dyn ValueView, runtime: &Runtime) -> Result<Value> {
+        let key = self.property.evaluate(runtime)?.to_kstr().to_string();
+        let mut items = items("sort_by", input)?;
+        sort_by_property(&mut items, &key, &self.timezone, |item| item);
+        Ok(Value::Array(
+            items.into_iter().map(|item| item.to_value()).collect(),
+        ))
+    }
+}
+
+/// `{{ global.articles | group_by: "config.date", "%Y" }}` is a list of
+/// `{name, items, size}` in the order each name first shows up. The optional format is for
+/// grouping dates, by year in this case
+#[derive(Debug, Clone)]
+struct GroupBy {
+    timezone: Timezone,
+}
+
+#[derive(Debug)]
+struct GroupByFilter {
+    property: Expression,
+    format: Option<Expression>,
+    timezone: Timezone,
+}
+
+reflection!(GroupBy => GroupByFilter, "group_by", "groups the items by a property");
+
+impl ParseFilter for GroupBy {
+    fn parse(&self, arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        let mut arguments = positional_arguments("group_by", arguments, 1, 1)?.into_iter();
+        Ok(Box::new(GroupByFilter {
+            property: arguments.next().unwrap(),
+            format: arguments.next(),
+            timezone: self.timezone,
+        }))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self
+    }
+}
+
+impl Filter for GroupByFilter {
+    fn evaluate(&self, input: &dyn ValueView, runtime: &Runtime) -> Result<Value> {
+        let key = self.property.evaluate(runtime)?.to_kstr().to_string();
+        let format = match &self.format {
+            Some(format) => Some(format.evaluate(runtime)?.to_kstr().to_string()),
+            None => None,
+        };
+
+        let mut groups: Vec<(String, Vec<Value>)> = Vec::new();
+        for item in items("group_by", input)? {
+            let name = match property(item, &key).filter(|value| !value.is_nil()) {
+                Some(value) => {
+                    let value = to_string(value);
+                    match &format {
+                        Some(format) => match parse_date(&value, &self.timezone) {
+                            Ok(date) => date.format(format).to_string(),
+                            Err(e) => {
+                                return Err(Error::with_msg(format!(
+                                    "group_by {:?} isn't a date, {}",
+                                    value, e
+                                )))
+                            }
+                        },
+                        None => value,
+                    }
+                }
+                None => String::new(),
+            };
+            match groups.iter_mut().find(|(group, _)| *group == name) {
+                Some((_, items)) => items.push(item.to_value()),
+                None => groups.push((name, vec![item.to_value()])),
+            }
+        }
+
+        Ok(Value::Array(
+            groups
+                .into_iter()
+                .map(|(name, items)| {
+                    let mut group = liquid_core::Object::new();
+                    group.insert("name".into(), Value::scalar(name));
+                    group.insert("size".into(), Value::scalar(items.len() as i64));
+                    group.insert("items".into(), Value::Array(items));
+                    Value::Object(group)
+                })
+                .collect(),
+        ))
+    }
+}
+
+/// `{{ page.config.title | slugify }}`, the same slugs mole uses for urls
+#[derive(Debug, Clone)]
+struct Slugify;
+
+reflection!(Slugify => Slugify, "slugify", "lowercase letters and numbers separated by '-'");
+
+impl ParseFilter for Slugify {
+    fn parse(&self, _arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self
+    }
+}
+
+impl Filter for Slugify {
+    fn evaluate(&self, input: &dyn ValueView, _runtime: &Runtime) -> Result<Value> {
+        Ok(Value::scalar(util::slugify(&input.to_kstr())))
+    }
+}
+
+/// `{{ page.config.description | markdownify }}` renders with the site's `[markdown]`
+/// settings, code blocks aren't highlighted
+#[derive(Debug, Clone)]
+struct Markdownify {
+    markdown: MarkdownConfig,
+}
+
+reflection!(Markdownify => Markdownify, "markdownify", "renders markdown to html");
+
+impl ParseFilter for Markdownify {
+    fn parse(&self, _arguments: FilterArguments) -> Result<Box<dyn Filter>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn reflection(&self) -> &dyn FilterReflection {
+        self
+    }
+}
+
+impl Filter for Markdownify {
+    fn evaluate(&self, input: &dyn ValueView, _runtime: &Runtime) -> Result<Value> {
+        Ok(Value::scalar(
+            markdown(&input.to_kstr(), &self.markdown, None).0,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod filter_tests {
+    use super::*;
+    use crate::article::Article;
+    use pretty_assertions::assert_eq;
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::path::Path;
+
+    fn render(template: &str, site: &SiteConfig) -> String {
+        render_with(template, site, liquid::object!({ "url": "/cats/" }))
+    }
+
+    fn render_with(template: &str, site: &SiteConfig, globals: liquid::Object) -> String {
+        register(liquid::ParserBuilder::with_stdlib(), site)
+            .build()
+            .unwrap()
+            .parse(template)
+            .unwrap()
+            .render(&globals)
+            .unwrap()
+    }
+
+    /// `global.articles` made from real articles like `Build::run` does
+    fn articles(site: &SiteConfig, articles: &[(&str, &str)]) -> liquid::Object {
+        let dir = tempfile::tempdir().unwrap();
+        let articles: Vec<liquid::model::Value> = articles
+            .iter()
+            .map(|(name, md)| {
+                let path = dir.path().join(name);
+                std::fs::write(&path, md).unwrap();
+                let article = Article::parse(
+                    BufReader::new(File::open(&path).unwrap()),
+                    &path,
+                    dir.path(),
+                    site,
+                )
+                .unwrap();
+                liquid::model::Value::Object(article.config_liquid)
+            })
+            .collect();
+        liquid::object!({ "global": { "articles": articles } })
+    }
+
+    #[test]
+    fn url_filters() {
+        let site = SiteConfig::parse(
+            "url = \"https://example.com/\"\nbaseurl = \"/project/\"\n",
+            Path::new("mole.toml"),
+        )
+        .unwrap();
+        assert_eq!(
+            "/project/cats/ https://example.com/project/cats/",
+            render("{{ url | relative_url }} {{ url | absolute_url }}", &site)
+        );
+        assert_eq!(
+            "https://mole.rs/",
+            render("{{ \"https://mole.rs/\" | relative_url }}", &site)
+        );
+    }
+
+    #[test]
+    fn url_filters_no_baseurl() {
+        let site = SiteConfig::default();
+        assert_eq!(
+            "/cats/ /main.css",
+            render(
+                "{{ url | relative_url }} {{ \"main.css\" | absolute_url }}",
+                &site
+            )
+        );
+    }
+
+    #[test]
+    fn article_filters() {
+        let site = SiteConfig::default();
+        let globals = articles(
+            &site,
+            &[
+                (
+                    "2020-03-01-b.md",
+                    "---\nlayout: page\ntitle: B\ndate: 2020-03-01 10:00\ntags: [rust]\n---\nb",
+                ),
+                (
+                    "2019-06-01-a.md",
+                    "---\nlayout: page\ntitle: A\ndate: 2019-06-01\ntags: [rust, web]\n---\na",
+                ),
+                (
+                    "2020-01-01-c.md",
+                    "---\nlayout: page\ntitle: C\ndate: 2020-01-01T12:00:00+01:00\n---\nc",
+                ),
+                ("d.md", "---\nlayout: page\ntitle: D\n---\nd"),
+            ],
+        );
+
+        assert_eq!(
+            "A C B D",
+            render_with(
+                "{{ global.articles | sort_by: \"config.date\" | map: \"config\" | map: \"title\" | join: \" \" }}",
+                &site,
+                globals.clone()
+            )
+        );
+        assert_eq!(
+            "B A",
+            render_with(
+                "{{ global.articles | where: \"config.tags\", \"rust\" | map: \"config\" | map: \"title\" | join: \" \" }}",
+                &site,
+                globals.clone()
+            )
+        );
+        assert_eq!(
+            "D",
+            render_with(
+                "{{ global.articles | where: \"url\", \"/d.html\" | map: \"config\" | map: \"title\" }}",
+                &site,
+                globals.clone()
+            )
+        );
+        assert_eq!(
+            "2020:2 2019:1 :1 ",
+            render_with(
+                "{% assign groups = global.articles | group_by: \"config.date\", \"%Y\" %}{% for group in groups %}{{ group.name }}:{{ group.size }} {% endfor %}",
+                &site,
+                globals.clone()
+            )
+        );
+        assert_eq!(
+            "2019-06-01T00:00:00+00:00",
+            render_with(
+                "{{ global.articles[1].config.date | date_to_xml_schema }}",
+                &site,
+                globals
+            )
+        );
+    }
+
+    #[test]
+    fn sort_by_kinds() {
+        let site = SiteConfig::default();
+        let sorted = |globals: liquid::Object| {
+            render_with(
+                "{{ items | sort_by: \"n\" | map: \"n\" | join: \" \" }}",
+                &site,
+                globals,
+            )
+        };
+        assert_eq!(
+            "9 10",
+            sorted(liquid::object!({ "items": [{ "n": 10 }, {}, { "n": "9" }] }))
+        );
+        assert_eq!(
+            "2019-06-01 2020-01-01T12:00:00+01:00",
+            sorted(
+                liquid::object!({ "items": [{ "n": "2020-01-01T12:00:00+01:00" }, { "n": "2019-06-01" }] })
+            )
+        );
+        // one value that isn't a number makes all of them text
+        assert_eq!(
+            "10 9 cats",
+            sorted(liquid::object!({ "items": [{ "n": "cats" }, { "n": 9 }, { "n": 10 }] }))
+        );
+        assert_eq!(
+            "2020-01-01 cats",
+            sorted(liquid::object!({ "items": [{ "n": "cats" }, { "n": "2020-01-01" }] }))
+        );
+    }
+
+    #[test]
+    fn text_filters() {
+        let mut site = SiteConfig::default();
+        site.markdown.smart_punctuation = true;
+        assert_eq!(
+            "cats-dogs-part-2 <p><em>cats</em> “and” dogs</p>\n",
+            render(
+                "{{ \"Cats & Dogs: Part 2!\" | slugify }} {{ '*cats* \"and\" dogs' | markdownify }}",
+                &site
+            )
+        );
+    }
+
+    #[test]
+    fn bad_arguments() {
+        let parser = register(liquid::ParserBuilder::with_stdlib(), &SiteConfig::default())
+            .build()
+            .unwrap();
+        assert!(parser.parse("{{ a | where: \"b\" }}").is_err());
+        assert!(parser.parse("{{ a | sort_by }}").is_err());
+        assert!(parser
+            .parse("{{ a | group_by: \"b\", \"%Y\", \"c\" }}")
+            .is_err());
+        assert!(parser
+            .parse("{{ \"cats\" | sort_by: \"b\" }}")
+            .unwrap()
+            .render(&liquid::object!({}))
+            .is_err());
+    }
+}
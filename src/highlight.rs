@@ -0,0 +1,516 @@
+//! build time syntax highlighting for fenced code blocks
+use crate::error::CustomError;
+use crate::util::escape_html;
+use log::warn;
+use pulldown_cmark::{CodeBlockKind, Event, Tag};
+use serde::Deserialize;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{
+    css_for_theme_with_class_style, line_tokens_to_classed_spans, styled_line_to_highlighted_html,
+    ClassStyle, IncludeBackground,
+};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+/// the classes are prefixed so they don't clash with the site's own css
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HighlightMode {
+    /// `style="color:..."` on every span, nothing else needed
+    Inline,
+    /// `class="hl-..."` on every span, needs the css from `mole highlight-css`
+    Classes,
+}
+
+/// `[highlight]` in mole.toml
+/// ```toml
+/// [highlight]
+/// mode = "classes"
+/// theme = "base16-ocean.dark"
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HighlightConfig {
+    pub enabled: bool,
+    pub mode: HighlightMode,
+    /// one of syntect's default themes
+    pub theme: String,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        HighlightConfig {
+            enabled: true,
+            mode: HighlightMode::Inline,
+            theme: String::from("InspiredGitHub"),
+        }
+    }
+}
+
+/// loading the syntaxes is slow so there is one of these per build
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+    mode: HighlightMode,
+}
+
+impl Highlighter {
+    pub fn new(config: &HighlightConfig) -> Result<Self, CustomError> {
+        let mut themes = ThemeSet::load_defaults();
+        let theme = match themes.themes.remove(&config.theme) {
+            Some(theme) => theme,
+            None => {
+                return Err(CustomError::ConfigError(format!(
+                    "[highlight] unknown theme {:?}, expected one of {}",
+                    config.theme,
+                    themes.themes.keys().cloned().collect::<Vec<_>>().join(", ")
+                )))
+            }
+        };
+
+        Ok(Highlighter {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme,
+            mode: config.mode,
+        })
+    }
+
+    /// the stylesheet for `mode = "classes"`
+    pub fn css(&self) -> Result<String, CustomError> {
+        css_for_theme_with_class_style(&self.theme, CLASS_STYLE)
+            .map_err(|e| CustomError::ConfigError(format!("[highlight] {}", e)))
+    }
+
+    /// the opening `<pre>` tag and each line on its own, every span a line opens is closed on
+    /// that line so they can be wrapped (line numbers and highlighted lines).
+    /// Unknown languages are left as plain text
+    pub fn highlight_lines(&self, code: &str, lang: &str) -> (String, Vec<String>) {
+        let syntax = self
+            .syntaxes
+            .find_syntax_by_token(lang)
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+
+        match self.mode {
+            HighlightMode::Inline => {
+                let pre = match self.theme.settings.background {
+                    Some(bg) => format!(
+                        "<pre class=\"highlight\" style=\"background-color:#{:02x}{:02x}{:02x};\">",
+                        bg.r, bg.g, bg.b
+                    ),
+                    None => String::from("<pre class=\"highlight\">"),
+                };
+                (pre, self.inline_lines(code, syntax))
+            }
+            HighlightMode::Classes => (
+                String::from("<pre class=\"highlight hl-code\">"),
+                self.classed_lines(code, syntax),
+            ),
+        }
+    }
+
+    fn inline_lines(&self, code: &str, syntax: &SyntaxReference) -> Vec<String> {
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+        LinesWithEndings::from(code)
+            .map(|line| {
+                highlighter
+                    .highlight_line(line, &self.syntaxes)
+                    .and_then(|regions| {
+                        styled_line_to_highlighted_html(&regions, IncludeBackground::No)
+                    })
+                    .map(|html| html.replace('\n', ""))
+                    .unwrap_or_else(|_| escape_html(line.trim_end_matches('\n')))
+            })
+            .collect()
+    }
+
+    fn classed_lines(&self, code: &str, syntax: &SyntaxReference) -> Vec<String> {
+        let mut state = ParseState::new(syntax);
+        let mut stack = ScopeStack::new();
+        let mut lines = Vec::new();
+        for line in LinesWithEndings::from(code) {
+            // reopen whatever the previous line left open
+            let mut html = String::new();
+            for scope in stack.as_slice() {
+                html += &format!("<span class=\"{}\">", scope_classes(&scope.build_string()));
+            }
+            let open = stack.as_slice().len() as isize;
+
+            let highlighted = state
+                .parse_line(line, &self.syntaxes)
+                .map_err(|e| e.to_string())
+                .and_then(|ops| {
+                    line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut stack)
+                        .map_err(|e| e.to_string())
+                });
+            match highlighted {
+                Ok((spans, delta)) => {
+                    html += &spans.replace('\n', "");
+                    for _ in 0..(open + delta) {
+                        html += "</span>";
+                    }
+                }
+                Err(_) => {
+                    html = escape_html(line.trim_end_matches('\n'));
+                    stack = ScopeStack::new();
+                }
+            }
+            lines.push(html);
+        }
+        lines
+    }
+}
+
+/// "source.rust" to "hl-source hl-rust" the same way syntect does it
+fn scope_classes(scope: &str) -> String {
+    scope
+        .split('.')
+        .map(|atom| format!("hl-{}", atom))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// everything in a fence's info string,
+/// ```` ```rust {linenos=true, hl_lines=[3,5-7], title="src/main.rs"} ````
+#[derive(Debug, Default, PartialEq)]
+pub struct CodeBlock {
+    pub lang: String,
+    pub linenos: bool,
+    /// 1-based inclusive ranges, only expanded once the number of lines in the block is known
+    pub hl_lines: Vec<(usize, usize)>,
+    pub title: Option<String>,
+}
+
+impl CodeBlock {
+    /// unknown or broken attributes are warned about and ignored
+    pub fn parse(info: &str) -> Self {
+        let (lang, attributes) = match info.find('{') {
+            Some(start) => (
+                &info[..start],
+                info[start + 1..].trim_end().trim_end_matches('}'),
+            ),
+            None => (info, ""),
+        };
+        let mut block = CodeBlock {
+            lang: lang.split_whitespace().next().unwrap_or("").to_string(),
+            ..CodeBlock::default()
+        };
+
+        for attribute in split_attributes(attributes) {
+            let (key, value) = match attribute.find('=') {
+                Some(i) => (attribute[..i].trim(), attribute[i + 1..].trim()),
+                None => (attribute.trim(), "true"),
+            };
+            let value = value.trim_matches('"');
+            match key {
+                "linenos" => match value {
+                    "true" => block.linenos = true,
+                    "false" => block.linenos = false,
+                    _ => warn!(
+                        "code block linenos should be true or false found {:?}",
+                        value
+                    ),
+                },
+                "hl_lines" => match parse_line_ranges(value) {
+                    Some(lines) => block.hl_lines = lines,
+                    None => warn!(
+                        "code block hl_lines should look like [3,5-7] found {:?}",
+                        value
+                    ),
+                },
+                "title" => block.title = Some(value.to_string()),
+                _ => warn!(
+                    "unknown code block attribute {:?}, expected linenos, hl_lines or title",
+                    key
+                ),
+            }
+        }
+        block
+    }
+
+    /// line numbers and highlighted lines wrap every line in `<span class="line">`
+    fn has_lines(&self) -> bool {
+        self.linenos || !self.hl_lines.is_empty()
+    }
+
+    /// ```html
+    /// <figure class="code-block"><figcaption class="code-title">src/main.rs</figcaption>
+    /// <pre class="highlight"><code class="language-rust"><span class="line hl"><span class="lineno">1</span>...</span>
+    /// </code></pre>
+    /// </figure>
+    /// ```
+    /// the figure is only there with a title and the line spans only with linenos or hl_lines
+    pub fn render(&self, code: &str, highlighter: Option<&Highlighter>) -> String {
+        let (pre, lines) = match highlighter {
+            Some(highlighter) => highlighter.highlight_lines(code, &self.lang),
+            None => (
+                String::from("<pre class=\"highlight\">"),
+                code.lines().map(escape_html).collect(),
+            ),
+        };
+
+        let mut html = String::new();
+        if let Some(title) = &self.title {
+            html += &format!(
+                "<figure class=\"code-block\"><figcaption class=\"code-title\">{}</figcaption>\n",
+                escape_html(title)
+            );
+        }
+        // a range past the end of the block is cut short before it's expanded
+        let mut highlighted = vec![false; lines.len()];
+        for &(start, end) in &self.hl_lines {
+            for line in start.max(1)..=end.min(lines.len()) {
+                highlighted[line - 1] = true;
+            }
+        }
+
+        html += &pre;
+        if self.lang.is_empty() {
+            html += "<code>";
+        } else {
+            html += &format!("<code class=\"language-{}\">", escape_html(&self.lang));
+        }
+        for (i, line) in lines.iter().enumerate() {
+            if self.has_lines() {
+                if highlighted[i] {
+                    html += "<span class=\"line hl\">";
+                } else {
+                    html += "<span class=\"line\">";
+                }
+                if self.linenos {
+                    html += &format!("<span class=\"lineno\">{}</span>", i + 1);
+                }
+                html += line;
+                html += "</span>";
+            } else {
+                html += line;
+            }
+            html.push('\n');
+        }
+        html += "</code></pre>\n";
+        if self.title.is_some() {
+            html += "</figure>\n";
+        }
+        html
+    }
+}
+
+/// splits on the commas that aren't inside [] or quotes
+fn split_attributes(attributes: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in attributes.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '[' if !quoted => depth += 1,
+            ']' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                parts.push(&attributes[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&attributes[start..]);
+    parts.into_iter().filter(|p| !p.trim().is_empty()).collect()
+}
+
+/// "[3,5-7]", "3 5-7" or "3" to [3, 5, 6, 7]
+fn parse_line_ranges(value: &str) -> Option<Vec<(usize, usize)>> {
+    let mut lines = Vec::new();
+    let value = value.trim_start_matches('[').trim_end_matches(']');
+    for range in value.split(|c: char| c == ',' || c.is_whitespace()) {
+        let range = range.trim().trim_matches('"');
+        if range.is_empty() {
+            continue;
+        }
+        match range.find('-') {
+            Some(i) => {
+                let start = range[..i].trim().parse::<usize>().ok()?;
+                let end = range[i + 1..].trim().parse::<usize>().ok()?;
+                lines.push((start, end));
+            }
+            None => {
+                let line = range.parse::<usize>().ok()?;
+                lines.push((line, line));
+            }
+        }
+    }
+    Some(lines)
+}
+
+/// swaps fenced code blocks for highlighted html, everything else passes through.
+/// Without a highlighter only blocks with linenos, hl_lines or a title are changed
+pub fn highlight_code_blocks<'a>(
+    events: Vec<Event<'a>>,
+    highlighter: Option<&Highlighter>,
+) -> Vec<Event<'a>> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut code: Option<(CodeBlock, String)> = None;
+    for event in events {
+        match (event, code.as_mut()) {
+            (Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))), None) => {
+                let block = CodeBlock::parse(&info);
+                if highlighter.is_some() || block.has_lines() || block.title.is_some() {
+                    code = Some((block, String::new()));
+                } else {
+                    output.push(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))));
+                }
+            }
+            (Event::Text(text), Some((_, content))) => content.push_str(&text),
+            (Event::End(Tag::CodeBlock(_)), Some(_)) => {
+                if let Some((block, content)) = code.take() {
+                    output.push(Event::Html(block.render(&content, highlighter).into()));
+                }
+            }
+            (event, _) => output.push(event),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod highlight_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::{html, Parser};
+
+    fn render(md: &str, mode: HighlightMode) -> String {
+        let highlighter = Highlighter::new(&HighlightConfig {
+            mode,
+            ..HighlightConfig::default()
+        })
+        .unwrap();
+        let mut output = String::new();
+        html::push_html(
+            &mut output,
+            highlight_code_blocks(Parser::new(md).collect(), Some(&highlighter)).into_iter(),
+        );
+        output
+    }
+
+    #[test]
+    fn inline() {
+        let output = render("```rust\nfn main() {}\n```\n", HighlightMode::Inline);
+        assert!(output.starts_with("<pre class=\"highlight\" style=\"background-color:#"));
+        assert!(output.contains("<code class=\"language-rust\"><span style=\""));
+        assert!(output.ends_with("</code></pre>\n"));
+    }
+
+    #[test]
+    fn classes() {
+        let output = render("```rust\nfn main() {}\n```\n", HighlightMode::Classes);
+        assert!(output.starts_with(
+            "<pre class=\"highlight hl-code\"><code class=\"language-rust\"><span class=\"hl-source hl-rust\">"
+        ));
+        assert_eq!(
+            output.matches("<span").count(),
+            output.matches("</span>").count()
+        );
+    }
+
+    #[test]
+    fn unknown_language() {
+        assert_eq!(
+            "<pre class=\"highlight hl-code\"><code class=\"language-cats\"><span class=\"hl-text hl-plain\">a &lt; b</span>\n</code></pre>\n",
+            render("```cats\na < b\n```\n", HighlightMode::Classes)
+        );
+    }
+
+    #[test]
+    fn indented_code_untouched() {
+        assert_eq!(
+            "<pre><code>a &lt; b\n</code></pre>\n",
+            render("    a < b\n", HighlightMode::Classes)
+        );
+    }
+
+    fn render_plain(md: &str) -> String {
+        let mut output = String::new();
+        html::push_html(
+            &mut output,
+            highlight_code_blocks(Parser::new(md).collect(), None).into_iter(),
+        );
+        output
+    }
+
+    #[test]
+    fn parse_code_block() {
+        assert_eq!(
+            CodeBlock {
+                lang: "rust".to_string(),
+                linenos: true,
+                hl_lines: vec![(3, 3), (5, 7)],
+                title: Some("src/main.rs".to_string()),
+            },
+            CodeBlock::parse("rust {linenos=true, hl_lines=[3,5-7], title=\"src/main.rs\"}")
+        );
+        assert_eq!(
+            CodeBlock {
+                lang: "rust".to_string(),
+                ..CodeBlock::default()
+            },
+            CodeBlock::parse("rust")
+        );
+        assert_eq!(
+            vec![(1, 1), (2, 2)],
+            CodeBlock::parse("{hl_lines=\"1 2\"}").hl_lines
+        );
+        assert_eq!(
+            Vec::<(usize, usize)>::new(),
+            CodeBlock::parse("rust {hl_lines=[a]}").hl_lines
+        );
+    }
+
+    #[test]
+    fn code_block_lines() {
+        assert_eq!(
+            "<figure class=\"code-block\"><figcaption class=\"code-title\">a &lt;b&gt;.txt</figcaption>\n<pre class=\"highlight\"><code class=\"language-txt\"><span class=\"line\"><span class=\"lineno\">1</span>a</span>\n<span class=\"line hl\"><span class=\"lineno\">2</span>&lt;b&gt;</span>\n</code></pre>\n</figure>\n",
+            render_plain("```txt {linenos=true, hl_lines=[2], title=\"a <b>.txt\"}\na\n<b>\n```\n")
+        );
+    }
+
+    #[test]
+    fn code_block_lines_past_the_end() {
+        assert_eq!(
+            "<pre class=\"highlight\"><code><span class=\"line\">a</span>\n<span class=\"line hl\">b</span>\n</code></pre>\n",
+            render_plain("```{hl_lines=[0,2-4000000000]}\na\nb\n```\n")
+        );
+    }
+
+    #[test]
+    fn code_block_untouched_without_highlighter() {
+        assert_eq!(
+            "<pre><code class=\"language-txt\">a\n</code></pre>\n",
+            render_plain("```txt\na\n```\n")
+        );
+    }
+
+    #[test]
+    fn code_block_lines_highlighted() {
+        let output = render(
+            "```rust {hl_lines=[2]}\n/* a\nb */\n```\n",
+            HighlightMode::Classes,
+        );
+        assert!(output.contains("<span class=\"line hl\"><span class=\"hl-source hl-rust\"><span class=\"hl-comment hl-block hl-rust\">b <span"), "{}", output);
+        assert_eq!(
+            output.matches("<span").count(),
+            output.matches("</span>").count()
+        );
+    }
+
+    #[test]
+    fn css() {
+        let highlighter = Highlighter::new(&HighlightConfig::default()).unwrap();
+        assert!(highlighter.css().unwrap().contains(".hl-code"));
+        assert!(Highlighter::new(&HighlightConfig {
+            theme: "cats".to_string(),
+            ..HighlightConfig::default()
+        })
+        .is_err());
+    }
+}
@@ -1,16 +1,32 @@
+pub mod admonition;
 pub mod article;
+pub mod date;
+use chrono::Utc;
 use log::{error, info, warn};
 use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::fs::File;
-use std::io::{prelude::*, BufReader};
+use std::io::BufReader;
 use std::path::{Path, PathBuf};
 pub mod error;
+pub mod filters;
+pub mod highlight;
 pub mod parse;
+pub mod site;
+pub mod tags;
+pub mod toc;
+pub mod typography;
 mod util;
 
 pub type Partials = liquid::partials::EagerCompiler<liquid::partials::InMemorySource>;
 
+/// compiled by `Build::sass` but only written out in `Build::run`
+struct Stylesheet {
+    source: PathBuf,
+    output: PathBuf,
+    css: String,
+}
+
 pub struct Build<'a> {
     includes: Partials,
     articles: Vec<article::Article>,
@@ -18,7 +34,14 @@ pub struct Build<'a> {
     output: &'a PathBuf,
 
     backtrace: bool,
+    drafts: bool,
+    future: bool,
+    site: site::SiteConfig,
     article_paths: Vec<String>,
+    /// drafts, future, expired and unpublished articles that were left out and why
+    skipped: Vec<String>,
+    stylesheets: Vec<Stylesheet>,
+    shortcodes: Vec<tags::Shortcode>,
     includes_paths: HashMap<String, String>,
 }
 
@@ -30,11 +53,36 @@ impl<'a> Build<'a> {
             articles: Vec::new(),
             output,
             backtrace,
+            drafts: false,
+            future: false,
+            site: site::SiteConfig::default(),
             article_paths: Vec::new(),
+            skipped: Vec::new(),
+            stylesheets: Vec::new(),
+            shortcodes: Vec::new(),
             includes_paths: HashMap::new(),
         }
     }
 
+    /// mole.toml, the non build settings are available in templates as `site`.
+    /// Needs to be set before loading articles for the defaults to apply
+    pub fn site(mut self, site: &site::SiteConfig) -> Self {
+        self.site = site.clone();
+        self
+    }
+
+    /// include articles with `draft: true`, needs to be set before loading articles
+    pub fn drafts(mut self, drafts: bool) -> Self {
+        self.drafts = drafts;
+        self
+    }
+
+    /// include articles with a `date` in the future, needs to be set before loading articles
+    pub fn future(mut self, future: bool) -> Self {
+        self.future = future;
+        self
+    }
+
     /// note: includes are hard-coded as .html files
     /// in util:search_dir and util::path_file_name_to_string
     pub fn includes(mut self, dir: &'a PathBuf, layout: bool) -> Self {
@@ -81,38 +129,135 @@ impl<'a> Build<'a> {
         self
     }
 
+    /// `name.html` templates that become `{% name %}` tags, the directory is optional
+    pub fn shortcodes(mut self, dir: &'a PathBuf) -> Self {
+        if !dir.is_dir() {
+            info!("no shortcodes directory {:?}", dir);
+            return self;
+        }
+        for file_path in util::search_dir(dir, "html", false) {
+            match (
+                util::path_file_name_to_string(&file_path),
+                util::read_file(&file_path),
+            ) {
+                (Ok(name), Ok(source)) => {
+                    info!("new shortcode {:?}", name);
+                    self.shortcodes.push(tags::Shortcode { name, source });
+                }
+                (Err(e), _) | (_, Err(e)) => error!("{:?} {}", file_path, e),
+            }
+        }
+        self
+    }
+
     pub fn articles(mut self, temp: &'a Vec<&'a PathBuf>) -> Self {
         for dir in temp {
-            info!("looking for markdown articles in {:?}", dir);
+            info!("looking for articles and pages in {:?}", dir);
             if dir.exists() && dir.is_dir() {
-                if self.layouts.is_empty() {
-                    panic!(
+                // collections inside the articles folder are loaded by `collections`
+                let files = self
+                    .find_articles(dir)
+                    .into_iter()
+                    .filter(|f| self.site.collection_for(f).is_none())
+                    .collect();
+                self.load_articles(dir, files);
+            } else {
+                error!("{:?} is not a path or directory", dir);
+            }
+        }
+
+        self
+    }
+
+    /// the `[collections]` from mole.toml, relative to the project root. A missing directory
+    /// is just an empty collection
+    pub fn collections(mut self) -> Self {
+        let dirs: Vec<PathBuf> = self
+            .site
+            .collections
+            .iter()
+            .map(|collection| self.site.root.join(&collection.dir))
+            .collect();
+        for dir in dirs {
+            info!("looking for collection articles in {:?}", dir);
+            if dir.is_dir() {
+                let files = self.find_articles(&dir);
+                self.load_articles(&dir, files);
+            } else {
+                warn!("no collection directory {:?}", dir);
+            }
+        }
+        self
+    }
+
+    /// markdown files and any pages with front matter
+    fn find_articles(&self, dir: &PathBuf) -> Vec<PathBuf> {
+        if self.layouts.is_empty() {
+            panic!(
                 "empty layout list, please load in layout template files before parsing articles"
             );
-                } else {
-                    for f in util::search_dir(&dir, "md", true) {
-                        if let Ok(cat) = File::open(&f) {
-                            match article::Article::parse(BufReader::new(cat), &f) {
-                                Ok(art) => {
-                                    self.articles.push(art);
-                                    self.article_paths.push(format!("{:?}", &f));
-                                }
-                                Err(e) => error!("{:?}", e),
+        }
+        // sub directories of the articles folder are searched as well so that
+        // defaults can be scoped to them, the project root itself is not
+        let mut files = Vec::new();
+        for extension in ["md"].iter().chain(&article::PAGE_EXTENSIONS) {
+            let mut found = if *dir == self.site.root {
+                util::search_dir(dir, extension, true)
+            } else {
+                util::walk_dir(dir, extension)
+            };
+            // other html and txt files could just be static files
+            if *extension != "md" {
+                found.retain(|f| article::has_front_matter(f));
+            }
+            files.append(&mut found);
+        }
+        files
+    }
+
+    /// `dir` is where they were found, pages keep their path in it
+    fn load_articles(&mut self, dir: &Path, files: Vec<PathBuf>) {
+        for f in files {
+            if let Ok(cat) = File::open(&f) {
+                match article::Article::parse(BufReader::new(cat), &f, dir, &self.site) {
+                    Ok(art) => {
+                        // left out here so they never end up in global.articles, tags or cats
+                        match art
+                            .config
+                            .skip_reason(&Utc::now(), self.drafts, self.future)
+                        {
+                            Some(reason) => {
+                                info!("skipping {:?} {}", &f, reason);
+                                self.skipped.push(format!("{:?} ({})", &f, reason));
+                            }
+                            None => {
+                                self.articles.push(art);
+                                self.article_paths.push(format!("{:?}", &f));
                             }
-                        } else {
-                            error!("Could not read {:?}", &f);
                         }
                     }
+                    Err(e) => error!("{:?}", e),
                 }
             } else {
-                error!("{:?} is not a path or directory", dir);
+                error!("Could not read {:?}", &f);
             }
         }
+    }
 
-        self
+    /// false for articles in a collection with `output = false`
+    fn writes(&self, art: &article::Article) -> bool {
+        match &art.config.collection {
+            Some(name) => self
+                .site
+                .collection(name)
+                .map(|collection| collection.output)
+                .unwrap_or(true),
+            None => true,
+        }
     }
 
-    pub fn sass(self, dir: &'a PathBuf, load_paths: &Vec<&Path>) -> Self {
+    /// the css is written out by `run` along with the articles
+    pub fn sass(mut self, dir: &'a PathBuf, load_paths: &Vec<&Path>) -> Self {
         if dir.exists() && dir.is_dir() {
             for f in util::search_dir(dir, "scss", true) {
                 if let Ok(data) = read_to_string(&f) {
@@ -124,10 +269,13 @@ impl<'a> Build<'a> {
                             let mut output_path = self.output.clone();
                             output_path
                                 .push(Path::new(&util::path_file_name_to_string(&f).unwrap()));
-                            info!("writing css to {:?}", output_path);
+                            output_path.set_extension("css");
 
-                            let mut file = File::create(output_path).unwrap();
-                            file.write_all(css.as_bytes()).unwrap();
+                            self.stylesheets.push(Stylesheet {
+                                source: f,
+                                output: output_path,
+                                css,
+                            });
                         }
                         Err(e) => warn!("parsing sccs {:?} caused {:?}", &f, e),
                     }
@@ -141,19 +289,84 @@ impl<'a> Build<'a> {
         self
     }
 
-    pub fn run(self) {
+    /// every file the build is going to write, checked before anything is written so that
+    /// two sources can't silently overwrite each other
+    fn check_outputs(&self) -> Result<(), error::CustomError> {
+        let mut outputs: HashMap<PathBuf, Vec<String>> = HashMap::new();
+        for (art, source) in self.articles.iter().zip(&self.article_paths) {
+            if !self.writes(art) {
+                continue;
+            }
+            outputs
+                .entry(art.output_path(self.output))
+                .or_default()
+                .push(source.clone());
+        }
+        for sheet in &self.stylesheets {
+            outputs
+                .entry(sheet.output.clone())
+                .or_default()
+                .push(format!("{:?}", sheet.source));
+        }
+
+        let mut collisions: Vec<String> = outputs
+            .into_iter()
+            .filter(|(_, sources)| sources.len() > 1)
+            .map(|(output, sources)| {
+                format!(
+                    "{:?} would be written by:\n   {}",
+                    output,
+                    sources.join("\n   ")
+                )
+            })
+            .collect();
+
+        if collisions.is_empty() {
+            Ok(())
+        } else {
+            collisions.sort();
+            Err(error::CustomError::OutputError(collisions.join("\n")))
+        }
+    }
+
+    /// fails without writing anything if two sources would end up at the same output path
+    pub fn run(self) -> Result<(), error::CustomError> {
         info!("run");
+        self.check_outputs()?;
+        // before the includes are moved into the parser
+        let writes: Vec<bool> = self.articles.iter().map(|art| self.writes(art)).collect();
         let mut global_articles: Vec<&liquid::Object> = Vec::new();
+        let mut global_collections: HashMap<&str, Vec<&liquid::Object>> = self
+            .site
+            .collections
+            .iter()
+            .map(|collection| (collection.name.as_str(), Vec::new()))
+            .collect();
         let mut global_tags: HashMap<&str, Vec<&str>> = HashMap::new();
         let mut global_cats: HashMap<&str, Vec<&str>> = HashMap::new();
 
-        let parser = liquid::ParserBuilder::with_stdlib()
+        let parser = liquid::ParserBuilder::with_stdlib();
+        let parser = tags::register(filters::register(parser, &self.site), &self.site);
+        let parser = tags::register_shortcodes(parser, &self.shortcodes)
             .partials(self.includes)
             .build()
             .unwrap();
 
-        for obj in &self.articles {
-            global_articles.push(&obj.config_liquid);
+        let highlighter = if self.site.highlight.enabled {
+            Some(highlight::Highlighter::new(&self.site.highlight)?)
+        } else {
+            None
+        };
+
+        // unlisted articles are still rendered below, they just don't show up in listings
+        for obj in self.articles.iter().filter(|a| a.config.listed) {
+            match &obj.config.collection {
+                Some(name) => global_collections
+                    .entry(name)
+                    .or_default()
+                    .push(&obj.config_liquid),
+                None => global_articles.push(&obj.config_liquid),
+            }
             for tag in &obj.config.tags {
                 global_tags.entry(tag).or_insert(Vec::new()).push(&obj.url);
             }
@@ -163,11 +376,23 @@ impl<'a> Build<'a> {
             }
         }
 
+        let timezone = &self.site.timezone;
+        for collection in &self.site.collections {
+            if let Some(articles) = global_collections.get_mut(collection.name.as_str()) {
+                let key = format!("config.{}", collection.sort_by);
+                filters::sort_by_property(articles, &key, timezone, |article| article);
+                if collection.reverse {
+                    articles.reverse();
+                }
+            }
+        }
+
         // One of the key things here is that articles is the raw content, that means it's nothing rendered yet
         // otherwise you would get weird things if you try to depend on something being already being renedered.
         // Although the cost of that is that we have to do the pre_render() step twice.
         let global = liquid::object!({
             "articles": global_articles,
+            "collections": global_collections,
             "tags": global_tags,
             "cats": global_cats,
         });
@@ -179,18 +404,27 @@ impl<'a> Build<'a> {
         info!("layouts: {:?}", self.layouts);
 
         let mut errors: HashMap<String, Vec<String>> = HashMap::new();
+        let mut built = 0;
         let mut i = 0;
         for art in self.articles {
-            //TODO: make this be the url
-            let mut output_path = self.output.clone();
-            output_path.push(PathBuf::from(&art.url));
+            if !writes[i] {
+                info!(
+                    "not writing {} (collection output = false)",
+                    self.article_paths[i]
+                );
+                i += 1;
+                continue;
+            }
+            let output_path = art.output_path(self.output);
             info!("writing to {:?}", output_path);
 
-            match &art.true_render(&global, &parser) {
+            match &art.true_render(&global, &self.site.variables, &parser, highlighter.as_ref()) {
                 Ok(output) => {
                     info!("success");
-                    let mut file = File::create(output_path).unwrap();
-                    file.write_all(output.as_bytes()).unwrap();
+                    match util::write_file(&output_path, output) {
+                        Ok(()) => built += 1,
+                        Err(e) => error!("{}file:\n   {}\n", e, self.article_paths[i]),
+                    }
                 }
                 Err(e) => match e {
                     error::CustomError::LiquidError(error) => {
@@ -204,7 +438,7 @@ impl<'a> Build<'a> {
                         }
                     }
 
-                    error::CustomError::IOError(_) => {}
+                    _ => {}
                 },
             }
 
@@ -228,6 +462,24 @@ impl<'a> Build<'a> {
                 }
             }
         }
+
+        for sheet in &self.stylesheets {
+            info!("writing css to {:?}", sheet.output);
+            if let Err(e) = util::write_file(&sheet.output, &sheet.css) {
+                error!("{}", e);
+            }
+        }
+
+        info!("built {} articles", built);
+        if !self.skipped.is_empty() {
+            info!(
+                "skipped {} articles:\n   {}",
+                self.skipped.len(),
+                self.skipped.join("\n   ")
+            );
+        }
+
+        Ok(())
     }
 }
 
@@ -261,3 +513,225 @@ fn parse_backtrace<'a>(error: &str, templates: &HashMap<String, String>) -> Stri
 
     msg
 }
+
+#[cfg(test)]
+mod build_tests {
+    use super::*;
+    use std::fs::{create_dir, write};
+
+    #[test]
+    fn output_collision() {
+        let dir = tempfile::tempdir().unwrap();
+        let layouts = dir.path().join("_layouts");
+        let articles = dir.path().join("_articles");
+        let scss = dir.path().join("_css");
+        let output = dir.path().join("_output");
+        create_dir(&layouts).unwrap();
+        create_dir(&articles).unwrap();
+        create_dir(&scss).unwrap();
+        write(layouts.join("page.html"), "{{page.content}}").unwrap();
+        write(layouts.join("default.html"), "{{page.content}}").unwrap();
+        write(
+            articles.join("a.md"),
+            "---\nlayout: page\ntitle: cats\n---\ncat",
+        )
+        .unwrap();
+        write(
+            articles.join("b.md"),
+            "---\nlayout: page\ntitle: Cats\n---\ncat",
+        )
+        .unwrap();
+        write(
+            articles.join("c.md"),
+            "---\nlayout: page\ntitle: dogs\npermalink: /main.css\n---\ndog",
+        )
+        .unwrap();
+        write(scss.join("main.scss"), "a { b { color: red; } }").unwrap();
+
+        let result = Build::new(&output, false)
+            .includes(&layouts, true)
+            .articles(&vec![&articles])
+            .sass(&scss, &vec![scss.as_path()])
+            .run();
+
+        match result {
+            Err(error::CustomError::OutputError(e)) => {
+                assert!(e.contains("cats.html"), "{}", e);
+                assert!(e.contains("a.md") && e.contains("b.md"), "{}", e);
+                assert!(e.contains("main.css") && e.contains("c.md"), "{}", e);
+                assert!(e.contains("main.scss"), "{}", e);
+            }
+            e => assert!(false, "looking for CustomError::OutputError found {:?}", e),
+        }
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn include_code_errors_only_fail_their_article() {
+        let dir = tempfile::tempdir().unwrap();
+        let layouts = dir.path().join("_layouts");
+        let articles = dir.path().join("_articles");
+        let output = dir.path().join("_output");
+        create_dir(&layouts).unwrap();
+        create_dir(&articles).unwrap();
+        write(layouts.join("page.html"), "{{page.content}}").unwrap();
+        write(layouts.join("default.html"), "{% include layout %}").unwrap();
+        write(
+            articles.join("a.md"),
+            "---\nlayout: page\ntitle: cats\n---\ncat",
+        )
+        .unwrap();
+        write(
+            articles.join("b.md"),
+            "---\nlayout: page\ntitle: dogs\n---\n{% include_code \"missing.rs\" %}",
+        )
+        .unwrap();
+
+        let mut site = site::SiteConfig::default();
+        site.root = dir.path().to_path_buf();
+        Build::new(&output, false)
+            .site(&site)
+            .includes(&layouts, true)
+            .articles(&vec![&articles])
+            .run()
+            .unwrap();
+
+        assert!(output.join("cats.html").exists());
+        assert!(!output.join("dogs.html").exists());
+    }
+
+    #[test]
+    fn pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let layouts = dir.path().join("_layouts");
+        let articles = dir.path().join("_articles");
+        let output = dir.path().join("_output");
+        create_dir(&layouts).unwrap();
+        create_dir(&articles).unwrap();
+        write(layouts.join("page.html"), "{{page.content}}").unwrap();
+        write(
+            layouts.join("default.html"),
+            "<body>{% include layout %}</body>",
+        )
+        .unwrap();
+        write(
+            articles.join("a.md"),
+            "---\nlayout: page\ntitle: cats\n---\n*cat*",
+        )
+        .unwrap();
+        write(
+            articles.join("feed.xml"),
+            "---\n---\n{% for a in global.articles %}<entry>{{a.url}}</entry>{% endfor %}",
+        )
+        .unwrap();
+        write(
+            articles.join("404.html"),
+            "---\nlayout: page\n---\n*not found*",
+        )
+        .unwrap();
+        write(articles.join("static.html"), "<p>as is</p>").unwrap();
+        create_dir(articles.join("docs")).unwrap();
+        write(articles.join("index.html"), "---\n---\nhome").unwrap();
+        write(articles.join("docs/index.html"), "---\n---\ndocs").unwrap();
+
+        Build::new(&output, false)
+            .includes(&layouts, true)
+            .articles(&vec![&articles])
+            .run()
+            .unwrap();
+
+        assert_eq!(
+            "<body><p><em>cat</em></p>\n</body>",
+            read_to_string(output.join("cats.html")).unwrap()
+        );
+        assert_eq!(
+            "<entry>/cats.html</entry>",
+            read_to_string(output.join("feed.xml")).unwrap()
+        );
+        assert_eq!(
+            "<body>*not found*</body>",
+            read_to_string(output.join("404.html")).unwrap()
+        );
+        assert!(!output.join("static.html").exists());
+        assert_eq!("home", read_to_string(output.join("index.html")).unwrap());
+        assert_eq!(
+            "docs",
+            read_to_string(output.join("docs/index.html")).unwrap()
+        );
+    }
+
+    #[test]
+    fn collections() {
+        let dir = tempfile::tempdir().unwrap();
+        let layouts = dir.path().join("_layouts");
+        let articles = dir.path().join("_articles");
+        let docs = dir.path().join("_docs");
+        let projects = articles.join("projects");
+        let output = dir.path().join("_output");
+        create_dir(&layouts).unwrap();
+        create_dir(&articles).unwrap();
+        create_dir(&docs).unwrap();
+        create_dir(&projects).unwrap();
+        write(layouts.join("default.html"), "{% include layout %}").unwrap();
+        write(layouts.join("page.html"), "{{page.content}}").unwrap();
+        write(
+            layouts.join("doc.html"),
+            "{{page.config.collection}}: {{page.content}}",
+        )
+        .unwrap();
+        write(
+            articles.join("index.md"),
+            "---\nlayout: page\ntitle: index\n---\n{% for a in global.articles %}{{a.url}} {% endfor %}\
+             {% for d in global.collections.docs %}{{d.url}} {% endfor %}\
+             {% for p in global.collections.projects %}{{p.config.title}} {% endfor %}",
+        )
+        .unwrap();
+        write(
+            docs.join("install.md"),
+            "---\ntitle: install\nweight: 2\n---\nrun it",
+        )
+        .unwrap();
+        write(
+            docs.join("about.md"),
+            "---\ntitle: about\nweight: 1\n---\nmoles",
+        )
+        .unwrap();
+        write(
+            projects.join("a.md"),
+            "---\nlayout: page\ntitle: a\ndate: 2020-01-01\n---\na",
+        )
+        .unwrap();
+        write(
+            projects.join("b.md"),
+            "---\nlayout: page\ntitle: b\ndate: 2021-01-01\n---\nb",
+        )
+        .unwrap();
+
+        let mut site = site::SiteConfig::parse(
+            "[collections.docs]\nlayout = \"doc\"\npermalink = \"/docs/:slug/\"\nsort_by = \"weight\"\n\n\
+             [collections.projects]\ndir = \"_articles/projects\"\nreverse = true\noutput = false\n",
+            Path::new("mole.toml"),
+        )
+        .unwrap();
+        site.root = dir.path().to_path_buf();
+
+        Build::new(&output, false)
+            .site(&site)
+            .includes(&layouts, true)
+            .articles(&vec![&articles])
+            .collections()
+            .run()
+            .unwrap();
+
+        assert_eq!(
+            "<p>/index.html /docs/about/ /docs/install/ b a </p>\n",
+            read_to_string(output.join("index.html")).unwrap()
+        );
+        assert_eq!(
+            "docs: <p>run it</p>\n",
+            read_to_string(output.join("docs/install/index.html")).unwrap()
+        );
+        assert!(!output.join("a.html").exists());
+        assert!(!output.join("projects").exists());
+    }
+}
@@ -1,4 +1,7 @@
-use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use crate::date::{parse_date, Timezone};
+use chrono::{DateTime, FixedOffset};
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 type ErrorMessage = String;
@@ -22,17 +25,18 @@ impl std::fmt::Debug for ParseError {
     }
 }
 
+/// `start` and `end` are 0 based byte offsets into `line`, the column printed is 1 based like rustc
 pub fn parse_error_message(
     message: &str,
     path: &PathBuf,
     line: &str,
     start: usize,
     end: usize,
-    lineno: i8,
+    lineno: usize,
 ) -> ErrorMessage {
-    let spacing = if lineno < 99 {
+    let spacing = if lineno < 100 {
         "  "
-    } else if lineno < 127 {
+    } else if lineno < 1000 {
         "   "
     } else {
         "    "
@@ -55,7 +59,7 @@ pub fn parse_error_message(
         w = spacing.len(),
         underline = underline,
         n = lineno,
-        start = start,
+        start = start + 1,
         m = message
     )
     .to_string();
@@ -63,40 +67,410 @@ pub fn parse_error_message(
     msg
 }
 
-pub fn parse_key<'a>(
-    rest: &'a str,
-    path: &PathBuf,
-    line: &str,
-    lineno: i8,
-) -> Result<(&'a str, &'a str), ParseError> {
-    if rest.is_empty() {
-        return Err(ParseError::EmptyValue(parse_error_message(
-            "expected name of key",
+/// The front matter block of an article, keeps hold of the raw lines so that errors
+/// found after the yaml or toml has been parsed can still point at the line the key was on.
+/// Both formats end up as yaml values so the rest of mole only has to deal with one of them
+pub struct FrontMatter<'a> {
+    path: &'a PathBuf,
+    lines: Vec<String>,
+    /// line number in the file of `lines[0]`
+    first_line: usize,
+    values: Mapping,
+    /// keys that came from mole.toml defaults rather than the file -> which rule set it
+    defaulted: HashMap<String, String>,
+}
+
+impl<'a> FrontMatter<'a> {
+    /// lines are everything in between the two '---' lines
+    pub fn yaml(
+        lines: Vec<String>,
+        path: &'a PathBuf,
+        first_line: usize,
+    ) -> Result<Self, ParseError> {
+        // mole has always allowed `layout:page` without the space yaml needs after a key
+        // so the space is added back in before handing it over to serde_yaml.
+        // `shifts` keeps track of where that happened so the columns in errors still line up
+        let mut shifts = Vec::new();
+        let mut document = String::new();
+        for line in &lines {
+            match compact_key(line) {
+                Some(index) => {
+                    document += &line[..=index];
+                    document += " ";
+                    document += &line[index + 1..];
+                    shifts.push(Some(index));
+                }
+                None => {
+                    document += line;
+                    shifts.push(None);
+                }
+            }
+            document += "\n";
+        }
+
+        // serde_yaml errors on an empty document rather than giving back null
+        if document.trim().is_empty() {
+            document.clear();
+            document += "{}";
+        }
+        let values = match serde_yaml::from_str::<Value>(&document) {
+            Ok(Value::Mapping(values)) => values,
+            Ok(Value::Null) => Mapping::new(),
+            Ok(_) => {
+                return Err(syntax_error(
+                    "expected the configuration to be a list of 'key: value' pairs",
+                    &lines,
+                    path,
+                    first_line,
+                    Some((0, 0)),
+                ))
+            }
+            Err(err) => {
+                // serde_yaml locations are 1 based
+                let location = err.location().and_then(|location| {
+                    let i = location.line().checked_sub(1)?;
+                    let mut column = location.column().saturating_sub(1);
+                    if let Some(Some(index)) = shifts.get(i) {
+                        if column > *index {
+                            column -= 1;
+                        }
+                    }
+                    Some((i, column))
+                });
+                return Err(syntax_error(
+                    &format!("yaml error: {}", err),
+                    &lines,
+                    path,
+                    first_line,
+                    location,
+                ));
+            }
+        };
+
+        Ok(FrontMatter {
+            path,
+            lines,
+            first_line,
+            values,
+            defaulted: HashMap::new(),
+        })
+    }
+
+    /// lines are everything in between the two '+++' lines
+    pub fn toml(
+        lines: Vec<String>,
+        path: &'a PathBuf,
+        first_line: usize,
+    ) -> Result<Self, ParseError> {
+        let document = lines.join("\n");
+        let values = match toml::from_str::<toml::Value>(&document) {
+            Ok(toml::Value::Table(table)) => match toml_to_yaml(toml::Value::Table(table)) {
+                Value::Mapping(values) => values,
+                _ => Mapping::new(),
+            },
+            Ok(_) => Mapping::new(),
+            Err(err) => {
+                return Err(syntax_error(
+                    &format!("toml error: {}", err),
+                    &lines,
+                    path,
+                    first_line,
+                    err.line_col(),
+                ))
+            }
+        };
+
+        Ok(FrontMatter {
             path,
+            lines,
+            first_line,
+            values,
+            defaulted: HashMap::new(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// only sets `key` if the article didn't, returns whether it was used
+    pub fn set_default(&mut self, key: &str, value: Value, rule: &str) -> bool {
+        let key_value = Value::String(key.to_string());
+        if self.values.contains_key(&key_value) {
+            return false;
+        }
+        self.values.insert(key_value, value);
+        self.defaulted.insert(key.to_string(), rule.to_string());
+        true
+    }
+
+    /// errors for values that came from defaults point at the first line of the
+    /// article, so say where the value actually came from
+    fn note(&self, key: &str, err: ParseError) -> ParseError {
+        let rule = match self.defaulted.get(key) {
+            Some(rule) => rule,
+            None => return err,
+        };
+        let note = format!("\n  note: '{}' was set by defaults rule {}", key, rule);
+        match err {
+            ParseError::InvalidKey(s) => ParseError::InvalidKey(s + &note),
+            ParseError::EmptyValue(s) => ParseError::EmptyValue(s + &note),
+            ParseError::InvalidValue(s) => ParseError::InvalidValue(s + &note),
+            ParseError::InvalidConfig(s) => ParseError::InvalidConfig(s + &note),
+        }
+    }
+
+    /// keys in the order they were written, non string keys are reported as errors
+    pub fn keys(&self) -> Result<Vec<String>, ParseError> {
+        let mut keys = Vec::new();
+        for (key, _) in &self.values {
+            match key {
+                Value::String(s) => keys.push(s.clone()),
+                _ => {
+                    return Err(ParseError::InvalidKey(
+                        self.error(&format!("{:?}", key), "keys need to be strings"),
+                    ))
+                }
+            }
+        }
+        Ok(keys)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(&Value::String(key.to_string()))
+    }
+
+    /// finds the line `key` was written on, returns (line, line number, start of the value)
+    /// works for both `key: value` and toml's `key = value`
+    fn locate(&self, key: &str) -> (&str, usize, usize) {
+        for (i, line) in self.lines.iter().enumerate() {
+            let after = match line.strip_prefix(key) {
+                Some(after) => after.trim_start(),
+                None => continue,
+            };
+            if after.starts_with(':') || after.starts_with('=') {
+                let separator = line.len() - after.len();
+                let rest = &line[separator + 1..];
+                let start = separator + 1 + (rest.len() - rest.trim_start().len());
+                return (line, self.first_line + i, start);
+            }
+        }
+        (&self.lines[0], self.first_line, 0)
+    }
+
+    /// error message pointing at the whole line `key` was found on
+    pub fn error(&self, key: &str, message: &str) -> ErrorMessage {
+        let (line, lineno, _) = self.locate(key);
+        let message = match self.defaulted.get(key) {
+            Some(rule) => format!("{} (set by defaults rule {})", message, rule),
+            None => message.to_string(),
+        };
+        parse_error_message(&message, self.path, line, 0, line.len(), lineno)
+    }
+
+    /// error message pointing at the value of `key`
+    fn value_error(&self, key: &str, message: &str) -> ErrorMessage {
+        let (line, lineno, start) = self.locate(key);
+        parse_error_message(
+            message,
+            self.path,
             line,
-            line.len(),
-            line.len() + 5,
+            start,
+            line.len().max(start + 1),
             lineno,
-        )));
+        )
     }
-    if let Some(index) = rest.find(":") {
-        return Ok((&rest[0..index], &rest[index + 1..]));
+
+    /// the value as it was written on the line, this is what the `parse_value_*` functions expect
+    fn scalar(&self, key: &str, value: &Value) -> Result<Option<String>, ParseError> {
+        match value {
+            Value::Null => Err(ParseError::EmptyValue(self.value_error(key, "empty value"))),
+            Value::String(s) => Ok(Some(s.clone())),
+            Value::Bool(b) => Ok(Some(b.to_string())),
+            Value::Number(n) => Ok(Some(n.to_string())),
+            Value::Sequence(_) => Ok(None),
+            Value::Mapping(_) => Ok(None),
+        }
     }
-    Err(ParseError::InvalidKey(parse_error_message(
-        "no semicolon found",
+
+    pub fn string(&self, key: &str) -> Result<String, ParseError> {
+        self.string_value(key).map_err(|e| self.note(key, e))
+    }
+
+    pub fn boolean(&self, key: &str) -> Result<bool, ParseError> {
+        self.boolean_value(key).map_err(|e| self.note(key, e))
+    }
+
+    /// `timezone` is used for dates that don't have an offset
+    pub fn time(
+        &self,
+        key: &str,
+        timezone: &Timezone,
+    ) -> Result<DateTime<FixedOffset>, ParseError> {
+        self.time_value(key, timezone)
+            .map_err(|e| self.note(key, e))
+    }
+
+    pub fn list(&self, key: &str) -> Result<Vec<String>, ParseError> {
+        self.list_value(key).map_err(|e| self.note(key, e))
+    }
+
+    fn string_value(&self, key: &str) -> Result<String, ParseError> {
+        let value = self.get(key).unwrap_or(&Value::Null);
+        match self.scalar(key, value)? {
+            Some(s) => {
+                if s.trim().is_empty() {
+                    Err(ParseError::EmptyValue(self.value_error(key, "empty value")))
+                } else {
+                    Ok(s)
+                }
+            }
+            None => Err(ParseError::InvalidValue(
+                self.value_error(key, "expected a string"),
+            )),
+        }
+    }
+
+    fn boolean_value(&self, key: &str) -> Result<bool, ParseError> {
+        match self.get(key) {
+            Some(Value::Bool(b)) => Ok(*b),
+            Some(value) => match self.scalar(key, value)? {
+                Some(s) => {
+                    let (line, lineno, start) = self.locate(key);
+                    parse_value_boolean(&s, self.path, line, start, lineno)
+                }
+                None => Err(ParseError::InvalidValue(
+                    self.value_error(key, "expected true or false"),
+                )),
+            },
+            None => Err(ParseError::EmptyValue(self.value_error(key, "empty value"))),
+        }
+    }
+
+    fn time_value(
+        &self,
+        key: &str,
+        timezone: &Timezone,
+    ) -> Result<DateTime<FixedOffset>, ParseError> {
+        let value = self.get(key).unwrap_or(&Value::Null);
+        match self.scalar(key, value)? {
+            Some(s) => {
+                let (line, lineno, start) = self.locate(key);
+                parse_value_time(s.trim(), self.path, line, start, lineno, timezone)
+            }
+            None => Err(ParseError::InvalidValue(
+                self.value_error(key, "expected a date"),
+            )),
+        }
+    }
+
+    /// lists can either be yaml lists or the older comma separated strings e.g. `tags: a, b`
+    fn list_value(&self, key: &str) -> Result<Vec<String>, ParseError> {
+        let value = self.get(key).unwrap_or(&Value::Null);
+        match value {
+            Value::Sequence(items) => {
+                let mut list = Vec::new();
+                for item in items {
+                    match self.scalar(key, item)? {
+                        Some(s) if !s.trim().is_empty() => list.push(s),
+                        Some(_) => {
+                            return Err(ParseError::EmptyValue(
+                                self.value_error(key, "empty value in list"),
+                            ))
+                        }
+                        None => {
+                            return Err(ParseError::InvalidValue(
+                                self.value_error(key, "lists can only contain strings"),
+                            ))
+                        }
+                    }
+                }
+                Ok(list)
+            }
+            _ => match self.scalar(key, value)? {
+                Some(s) => {
+                    let (line, lineno, _) = self.locate(key);
+                    parse_value_list(&s, self.path, line, lineno)
+                }
+                None => Err(ParseError::InvalidValue(
+                    self.value_error(key, "expected a list"),
+                )),
+            },
+        }
+    }
+}
+
+/// `location` is the 0 based (line, column) inside the front matter block
+fn syntax_error(
+    message: &str,
+    lines: &[String],
+    path: &PathBuf,
+    first_line: usize,
+    location: Option<(usize, usize)>,
+) -> ParseError {
+    // both libraries tack on their own line numbers which are relative to the block not the file
+    let message = match message.find(" at line ") {
+        Some(index) => &message[..index],
+        None => message,
+    };
+
+    let last = lines.len().saturating_sub(1);
+    let (i, column) = match location {
+        Some((i, column)) if i < lines.len() => (i, column),
+        _ => (last, 0),
+    };
+    let line = lines.get(i).map(|l| l.as_str()).unwrap_or("");
+    let column = column.min(line.len());
+    ParseError::InvalidConfig(parse_error_message(
+        message,
         path,
         line,
-        line.len(),
-        line.len() + 1,
-        lineno,
-    )))
+        column,
+        line.len().max(column + 1),
+        first_line + i,
+    ))
+}
+
+/// toml dates don't have a yaml equivalent so they are kept as strings, the same as yaml does
+pub fn toml_to_yaml(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::Number(i.into()),
+        toml::Value::Float(f) => Value::Number(f.into()),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(items) => Value::Sequence(items.into_iter().map(toml_to_yaml).collect()),
+        toml::Value::Table(table) => Value::Mapping(
+            table
+                .into_iter()
+                .map(|(k, v)| (Value::String(k), toml_to_yaml(v)))
+                .collect(),
+        ),
+    }
+}
+
+/// `layout:page` -> Some(index of ':'), only for top level keys
+fn compact_key(line: &str) -> Option<usize> {
+    let index = line.find(':')?;
+    let key = &line[..index];
+    let next = line[index + 1..].chars().next()?;
+    if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && !next.is_whitespace()
+    {
+        Some(index)
+    } else {
+        None
+    }
 }
 
 pub fn parse_value_string<'a>(
     rest: &'a str,
     path: &PathBuf,
     line: &str,
-    lineno: i8,
+    lineno: usize,
 ) -> Result<&'a str, ParseError> {
     let rest = rest.trim();
     if rest.is_empty() {
@@ -109,16 +483,6 @@ pub fn parse_value_string<'a>(
             lineno,
         )));
     }
-    if rest == "---" {
-        return Err(ParseError::InvalidValue(parse_error_message(
-            "found '---' can't use configuration start and end identifier as a value",
-            path,
-            line,
-            line.len() - 3,
-            line.len(),
-            lineno,
-        )));
-    }
     Ok(rest)
 }
 
@@ -126,16 +490,17 @@ pub fn parse_value_boolean(
     rest: &str,
     path: &PathBuf,
     line: &str,
-    lineno: i8,
+    start: usize,
+    lineno: usize,
 ) -> Result<bool, ParseError> {
-    match rest.parse::<bool>() {
+    match rest.trim().parse::<bool>() {
         Ok(b) => Ok(b),
         Err(_) => Err(ParseError::InvalidValue(parse_error_message(
-            "",
+            "expected true or false",
             path,
             line,
-            line.len() - rest.len(),
-            line.len(),
+            start,
+            line.len().max(start + 1),
             lineno,
         ))),
     }
@@ -145,21 +510,20 @@ pub fn parse_value_time(
     rest: &str,
     path: &PathBuf,
     line: &str,
-    lineno: i8,
-) -> Result<NaiveDateTime, ParseError> {
-    match NaiveDate::parse_from_str(rest, "%Y-%m-%d") {
-        Ok(date) => Ok(date.and_time(NaiveTime::from_hms_milli(0, 0, 0, 0))),
-        Err(_) => match NaiveDateTime::parse_from_str(rest, "%Y-%m-%d %H:%M") {
-            Ok(date) => Ok(date),
-            Err(err) => Err(ParseError::InvalidValue(parse_error_message(
-                &("date error: ".to_owned() + &err.to_string() + " expected Y-m-d or Y-m-d h:m"),
-                path,
-                line,
-                line.len() - rest.len(),
-                line.len(),
-                lineno,
-            ))),
-        },
+    start: usize,
+    lineno: usize,
+    timezone: &Timezone,
+) -> Result<DateTime<FixedOffset>, ParseError> {
+    match parse_date(rest, timezone) {
+        Ok(date) => Ok(date),
+        Err(err) => Err(ParseError::InvalidValue(parse_error_message(
+            &("date error: ".to_owned() + &err),
+            path,
+            line,
+            start,
+            line.len().max(start + 1),
+            lineno,
+        ))),
     }
 }
 
@@ -167,7 +531,7 @@ pub fn parse_value_list(
     mut rest: &str,
     path: &PathBuf,
     line: &str,
-    lineno: i8,
+    lineno: usize,
 ) -> Result<Vec<String>, ParseError> {
     rest = rest.trim();
     if rest.is_empty() {
@@ -251,25 +615,141 @@ mod parse_tests {
     use super::*;
     use pretty_assertions::assert_eq;
 
+    fn front_matter<'a>(yaml: &str, path: &'a PathBuf) -> Result<FrontMatter<'a>, ParseError> {
+        FrontMatter::yaml(yaml.lines().map(String::from).collect(), path, 2)
+    }
+
+    #[test]
+    fn yaml_compact_key() {
+        let path = PathBuf::from("test.txt");
+        let fm = front_matter("layout:page\ntitle: cats: and dogs", &path).err();
+        match fm {
+            Some(ParseError::InvalidConfig(config)) => assert!(
+                config.contains(" 3:"),
+                "expected error on line 3 in {}",
+                config
+            ),
+            _ => assert!(false, "expected error"),
+        }
+
+        let fm = front_matter("layout:page\ntitle: \"cats: and dogs\"", &path).unwrap();
+        assert_eq!("page", fm.string("layout").unwrap());
+        assert_eq!("cats: and dogs", fm.string("title").unwrap());
+    }
+
     #[test]
-    fn parse_key_test() {
-        let line = "hello: world";
-        let (key, rest) = parse_key(line, &PathBuf::from("test.txt"), line, 1).unwrap();
-        assert_eq!(key, "hello");
-        assert_eq!(rest, " world");
+    fn yaml_multi_line() {
+        let path = PathBuf::from("test.txt");
+        let fm = front_matter(
+            "description: >\n  cats\n  and dogs\ntags:\n  - a\n  - b\ncategories: a, b",
+            &path,
+        )
+        .unwrap();
+        assert_eq!("cats and dogs\n", fm.string("description").unwrap());
+        assert_eq!(vec!["a", "b"], fm.list("tags").unwrap());
+        assert_eq!(vec!["a", "b"], fm.list("categories").unwrap());
     }
 
     #[test]
-    fn parse_key_no_semicolon() {
-        let line = "hello  world";
-        let err = parse_key(line, &PathBuf::from("test.txt"), line, 1).err();
+    fn yaml_value_error_location() {
+        let path = PathBuf::from("test.txt");
+        let fm = front_matter("title: cats\nlayout: [a, b]", &path).unwrap();
+        match fm.string("layout").err() {
+            Some(ParseError::InvalidValue(config)) => assert!(
+                config.contains("test.txt 3:9"),
+                "expected 'test.txt 3:9' in {}",
+                config
+            ),
+            e => assert!(false, "expected error found {:?}", e),
+        }
+    }
+
+    #[test]
+    fn toml_front_matter() {
+        let path = PathBuf::from("test.txt");
+        let fm = FrontMatter::toml(
+            "title = \"cats: and dogs\"\ntags = [\"a\", \"b\"]\nlayout=\"page\"\n[author]\nname = \"mole\""
+                .lines()
+                .map(String::from)
+                .collect(),
+            &path,
+            2,
+        )
+        .unwrap();
+        assert_eq!("cats: and dogs", fm.string("title").unwrap());
+        assert_eq!("page", fm.string("layout").unwrap());
+        assert_eq!(vec!["a", "b"], fm.list("tags").unwrap());
+        assert!(fm.get("author").unwrap().is_mapping());
+        match fm.string("tags").err() {
+            Some(ParseError::InvalidValue(config)) => assert!(
+                config.contains("test.txt 3:8"),
+                "expected 'test.txt 3:8' in {}",
+                config
+            ),
+            e => assert!(false, "expected error found {:?}", e),
+        }
+    }
+
+    #[test]
+    fn toml_error_location() {
+        let path = PathBuf::from("test.txt");
+        let err = FrontMatter::toml(
+            vec!["title = \"cats\"".to_string(), "layout = ".to_string()],
+            &path,
+            2,
+        )
+        .err();
         match err {
-            Some(ParseError::InvalidKey(config)) => assert!(
-                config.contains("no semicolon found"),
-                "expected 'no semicolon found' in {}",
+            Some(ParseError::InvalidConfig(config)) => assert!(
+                config.contains("test.txt 3:") && !config.contains("at line"),
+                "expected error on line 3 in {}",
                 config
             ),
-            _ => assert!(false, "expected error"),
+            e => assert!(false, "expected error found {:?}", e),
+        }
+    }
+
+    #[test]
+    fn defaults_dont_override() {
+        let path = PathBuf::from("test.txt");
+        let mut fm = front_matter("layout: page", &path).unwrap();
+        assert!(!fm.set_default("layout", Value::String("doc".into()), "#1"));
+        assert!(fm.set_default("tags", Value::Mapping(Mapping::new()), "#1"));
+        assert_eq!("page", fm.string("layout").unwrap());
+        match fm.list("tags").err() {
+            Some(ParseError::InvalidValue(config)) => assert!(
+                config.contains("note: 'tags' was set by defaults rule #1"),
+                "expected defaults note in {}",
+                config
+            ),
+            e => assert!(false, "expected error found {:?}", e),
+        }
+    }
+
+    #[test]
+    fn time_timezone() {
+        let path = PathBuf::from("test.txt");
+        let fm = front_matter(
+            "date: 2020-07-01 10:30\nupdated: 2020-07-01T10:30:00Z\nbad: 2020-13-01",
+            &path,
+        )
+        .unwrap();
+        let timezone = "+02:00".parse().unwrap();
+        assert_eq!(
+            "2020-07-01T10:30:00+02:00",
+            fm.time("date", &timezone).unwrap().to_rfc3339()
+        );
+        assert_eq!(
+            "2020-07-01T10:30:00+00:00",
+            fm.time("updated", &timezone).unwrap().to_rfc3339()
+        );
+        match fm.time("bad", &timezone).err() {
+            Some(ParseError::InvalidValue(config)) => assert!(
+                config.contains("test.txt 4:6"),
+                "expected 'test.txt 4:6' in {}",
+                config
+            ),
+            e => assert!(false, "expected error found {:?}", e),
         }
     }
 
@@ -0,0 +1,613 @@
+use crate::admonition::AdmonitionClasses;
+use crate::date::Timezone;
+use crate::error::CustomError;
+use crate::highlight::HighlightConfig;
+use crate::parse::toml_to_yaml;
+use crate::util;
+use log::info;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// looked for in the root of the project in this order
+pub const CONFIG_FILES: [&str; 2] = ["mole.toml", ".mole.toml"];
+
+/// used when mole.toml doesn't set `excerpt_separator`
+pub const EXCERPT_SEPARATOR: &str = "<!--more-->";
+
+/// the `[build]` table, anything set here can be overridden by the matching `mole build` flag
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BuildConfig {
+    pub dest: Option<PathBuf>,
+    pub include: Option<PathBuf>,
+    pub layouts: Option<PathBuf>,
+    pub shortcodes: Option<PathBuf>,
+    pub articles: Option<PathBuf>,
+    pub scss: Option<PathBuf>,
+    pub scss_load_paths: Option<PathBuf>,
+    pub strict: Option<bool>,
+}
+
+/// `[markdown]`, which pulldown-cmark extensions are turned on, they all default to on like
+/// github flavoured markdown. `smart_punctuation` is mole's own pass for curly quotes and
+/// dashes, off unless asked for, and `admonitions` turns `> [!NOTE]` blockquotes into callouts, with the classes
+/// from `[markdown.admonition_classes]`. Articles can override them with a `markdown:` map
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarkdownConfig {
+    pub tables: bool,
+    pub footnotes: bool,
+    pub strikethrough: bool,
+    pub tasklists: bool,
+    pub smart_punctuation: bool,
+    pub admonitions: bool,
+    pub admonition_classes: AdmonitionClasses,
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> Self {
+        MarkdownConfig {
+            tables: true,
+            footnotes: true,
+            strikethrough: true,
+            tasklists: true,
+            smart_punctuation: false,
+            admonitions: true,
+            admonition_classes: AdmonitionClasses::default(),
+        }
+    }
+}
+
+impl MarkdownConfig {
+    /// false if there isn't an extension called `name`
+    pub fn set(&mut self, name: &str, on: bool) -> bool {
+        match name {
+            "tables" => self.tables = on,
+            "footnotes" => self.footnotes = on,
+            "strikethrough" => self.strikethrough = on,
+            "tasklists" => self.tasklists = on,
+            "smart_punctuation" => self.smart_punctuation = on,
+            "admonitions" => self.admonitions = on,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn options(&self) -> pulldown_cmark::Options {
+        let mut options = pulldown_cmark::Options::empty();
+        options.set(pulldown_cmark::Options::ENABLE_TABLES, self.tables);
+        options.set(pulldown_cmark::Options::ENABLE_FOOTNOTES, self.footnotes);
+        options.set(
+            pulldown_cmark::Options::ENABLE_STRIKETHROUGH,
+            self.strikethrough,
+        );
+        options.set(pulldown_cmark::Options::ENABLE_TASKLISTS, self.tasklists);
+        options
+    }
+}
+
+/// `[[defaults]]`, fills in any article config keys the article leaves unset.
+/// `path` is a directory relative to the project root and `glob` a pattern matched against
+/// the path of the article relative to the root, leaving both out matches everything
+/// ```toml
+/// [[defaults]]
+/// path = "_articles/docs"
+/// [defaults.values]
+/// layout = "doc"
+/// categories = ["docs"]
+/// ```
+#[derive(Debug, Clone)]
+pub struct DefaultRule {
+    /// position in mole.toml, used to say which rule set a value
+    pub index: usize,
+    pub path: Option<PathBuf>,
+    pub glob: Option<glob::Pattern>,
+    pub values: serde_yaml::Mapping,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDefaultRule {
+    path: Option<PathBuf>,
+    glob: Option<String>,
+    #[serde(default)]
+    values: toml::value::Table,
+}
+
+impl DefaultRule {
+    /// `path` is relative to the project root
+    pub fn matches(&self, path: &Path) -> bool {
+        if let Some(dir) = &self.path {
+            if !path.starts_with(dir) {
+                return false;
+            }
+        }
+        if let Some(glob) = &self.glob {
+            if !glob.matches_path(path) {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn describe(&self) -> String {
+        let mut scope = Vec::new();
+        if let Some(dir) = &self.path {
+            scope.push(format!("path = {:?}", dir));
+        }
+        if let Some(glob) = &self.glob {
+            scope.push(format!("glob = {:?}", glob.as_str()));
+        }
+        format!("#{} ({})", self.index + 1, scope.join(", "))
+    }
+}
+
+/// `[collections.<name>]`, a directory of articles that are listed in
+/// `global.collections.<name>` rather than `global.articles`
+/// ```toml
+/// [collections.docs]
+/// dir = "_docs"
+/// layout = "doc"
+/// permalink = "/docs/:slug/"
+/// sort_by = "weight"
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collection {
+    pub name: String,
+    /// relative to the project root, `_<name>` by default
+    pub dir: PathBuf,
+    /// for articles that don't set their own `layout` or `permalink` (or get one from `[[defaults]]`)
+    pub layout: Option<String>,
+    pub permalink: Option<String>,
+    /// a config key like the `sort_by` filter, `date` by default. Articles without it go last
+    pub sort_by: String,
+    pub reverse: bool,
+    /// `output = false` lists the articles without writing them out
+    pub output: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawCollection {
+    dir: Option<PathBuf>,
+    layout: Option<String>,
+    permalink: Option<String>,
+    sort_by: Option<String>,
+    #[serde(default)]
+    reverse: bool,
+    output: Option<bool>,
+}
+
+impl Collection {
+    /// `path` is relative to the project root
+    pub fn contains(&self, path: &Path) -> bool {
+        path.starts_with(&self.dir)
+    }
+}
+
+/// mole.toml
+/// ```toml
+/// title = "my site"
+/// url = "https://example.com"
+///
+/// [build]
+/// dest = "public/"
+/// ```
+#[derive(Debug, Clone)]
+pub struct SiteConfig {
+    /// the project directory, article paths are matched against defaults relative to this
+    pub root: PathBuf,
+    pub build: BuildConfig,
+    pub markdown: MarkdownConfig,
+    pub highlight: HighlightConfig,
+    pub defaults: Vec<DefaultRule>,
+    pub collections: Vec<Collection>,
+    /// `timezone`, used for article dates that don't have an offset. Defaults to UTC
+    pub timezone: Timezone,
+    /// `excerpt_separator`, everything before it is the article's excerpt
+    pub excerpt_separator: String,
+    /// `permalink`, the url pattern for articles with a date e.g. "/:year/:month/:slug/"
+    pub permalink: Option<String>,
+    /// `url`, e.g. "https://example.com" without a trailing '/', used by `absolute_url`
+    pub url: String,
+    /// `baseurl`, e.g. "/project" when the site isn't served from the root of the domain
+    pub baseurl: String,
+    /// `pretty_urls`, articles without a permalink go to `<slug>/index.html` rather than `<slug>.html`
+    pub pretty_urls: bool,
+    /// every top level key apart from `[build]`, `[markdown]`, `[highlight]`, `[[defaults]]` and `[collections]`, available in templates as `site`
+    pub variables: liquid::Object,
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        SiteConfig {
+            root: PathBuf::new(),
+            build: BuildConfig::default(),
+            markdown: MarkdownConfig::default(),
+            highlight: HighlightConfig::default(),
+            defaults: Vec::new(),
+            collections: Vec::new(),
+            timezone: Timezone::default(),
+            excerpt_separator: EXCERPT_SEPARATOR.to_string(),
+            permalink: None,
+            url: String::new(),
+            baseurl: String::new(),
+            pretty_urls: false,
+            variables: liquid::Object::new(),
+        }
+    }
+}
+
+impl SiteConfig {
+    /// a missing config file isn't an error, everything just falls back to the defaults
+    pub fn load(root: &Path) -> Result<Self, CustomError> {
+        for name in CONFIG_FILES.iter() {
+            let path = root.join(name);
+            if path.is_file() {
+                info!("using config {:?}", path);
+                let mut site = SiteConfig::parse(&util::read_file(&path)?, &path)?;
+                site.root = root.to_path_buf();
+                return Ok(site);
+            }
+        }
+        Ok(SiteConfig {
+            root: root.to_path_buf(),
+            ..SiteConfig::default()
+        })
+    }
+
+    pub fn strict(&self) -> bool {
+        self.build.strict.unwrap_or(false)
+    }
+
+    /// rules that apply to `path`, later rules in mole.toml come first so that they win
+    pub fn defaults_for(&self, path: &Path) -> Vec<&DefaultRule> {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        self.defaults
+            .iter()
+            .rev()
+            .filter(|rule| rule.matches(relative))
+            .collect()
+    }
+
+    /// the collection `path` is in, if any
+    pub fn collection_for(&self, path: &Path) -> Option<&Collection> {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        self.collections
+            .iter()
+            .find(|collection| collection.contains(relative))
+    }
+
+    pub fn collection(&self, name: &str) -> Option<&Collection> {
+        self.collections
+            .iter()
+            .find(|collection| collection.name == name)
+    }
+
+    pub fn parse(content: &str, path: &Path) -> Result<Self, CustomError> {
+        let mut table = match toml::from_str::<toml::Value>(content) {
+            Ok(toml::Value::Table(table)) => table,
+            Ok(_) => toml::value::Table::new(),
+            Err(e) => return Err(CustomError::ConfigError(format!("{:?} {}", path, e))),
+        };
+
+        let build = match table.remove("build") {
+            Some(build) => build
+                .try_into::<BuildConfig>()
+                .map_err(|e| CustomError::ConfigError(format!("{:?} [build] {}", path, e)))?,
+            None => BuildConfig::default(),
+        };
+
+        let markdown = match table.remove("markdown") {
+            Some(markdown) => markdown
+                .try_into::<MarkdownConfig>()
+                .map_err(|e| CustomError::ConfigError(format!("{:?} [markdown] {}", path, e)))?,
+            None => MarkdownConfig::default(),
+        };
+
+        let highlight = match table.remove("highlight") {
+            Some(highlight) => highlight
+                .try_into::<HighlightConfig>()
+                .map_err(|e| CustomError::ConfigError(format!("{:?} [highlight] {}", path, e)))?,
+            None => HighlightConfig::default(),
+        };
+
+        let mut defaults = Vec::new();
+        if let Some(rules) = table.remove("defaults") {
+            let rules = rules
+                .try_into::<Vec<RawDefaultRule>>()
+                .map_err(|e| CustomError::ConfigError(format!("{:?} [[defaults]] {}", path, e)))?;
+            for (index, rule) in rules.into_iter().enumerate() {
+                let glob = match rule.glob {
+                    Some(glob) => Some(glob::Pattern::new(&glob).map_err(|e| {
+                        CustomError::ConfigError(format!(
+                            "{:?} [[defaults]] #{} glob {:?} {}",
+                            path,
+                            index + 1,
+                            glob,
+                            e
+                        ))
+                    })?),
+                    None => None,
+                };
+                let values = match toml_to_yaml(toml::Value::Table(rule.values)) {
+                    serde_yaml::Value::Mapping(values) => values,
+                    _ => serde_yaml::Mapping::new(),
+                };
+                defaults.push(DefaultRule {
+                    index,
+                    path: rule.path,
+                    glob,
+                    values,
+                });
+            }
+        }
+
+        let mut collections = Vec::new();
+        if let Some(raw) = table.remove("collections") {
+            let raw = raw
+                .try_into::<BTreeMap<String, RawCollection>>()
+                .map_err(|e| CustomError::ConfigError(format!("{:?} [collections] {}", path, e)))?;
+            for (name, collection) in raw {
+                collections.push(Collection {
+                    dir: collection
+                        .dir
+                        .unwrap_or_else(|| PathBuf::from(format!("_{}", name))),
+                    layout: collection.layout,
+                    permalink: collection.permalink,
+                    sort_by: collection.sort_by.unwrap_or_else(|| "date".to_string()),
+                    reverse: collection.reverse,
+                    output: collection.output.unwrap_or(true),
+                    name,
+                });
+            }
+        }
+
+        let timezone = match table.get("timezone") {
+            Some(toml::Value::String(timezone)) => timezone
+                .parse::<Timezone>()
+                .map_err(|e| CustomError::ConfigError(format!("{:?} timezone {}", path, e)))?,
+            Some(_) => {
+                return Err(CustomError::ConfigError(format!(
+                    "{:?} timezone needs to be a string e.g. \"Europe/London\" or \"+01:00\"",
+                    path
+                )))
+            }
+            None => Timezone::default(),
+        };
+
+        let excerpt_separator = match table.get("excerpt_separator") {
+            Some(toml::Value::String(separator)) if !separator.is_empty() => separator.clone(),
+            Some(_) => {
+                return Err(CustomError::ConfigError(format!(
+                    "{:?} excerpt_separator needs to be a non empty string e.g. \"<!--more-->\"",
+                    path
+                )))
+            }
+            None => EXCERPT_SEPARATOR.to_string(),
+        };
+
+        let permalink = match table.get("permalink") {
+            Some(toml::Value::String(permalink)) => Some(permalink.clone()),
+            Some(_) => {
+                return Err(CustomError::ConfigError(format!(
+                    "{:?} permalink needs to be a string e.g. \"/:year/:month/:slug/\"",
+                    path
+                )))
+            }
+            None => None,
+        };
+
+        // "/project/" and "project" both become "/project" so the filters can just join them
+        let (url, baseurl) = match (table.get("url"), table.get("baseurl")) {
+            (None, None) => (String::new(), String::new()),
+            (Some(toml::Value::String(url)), None) => {
+                (url.trim_end_matches('/').to_string(), String::new())
+            }
+            (None, Some(toml::Value::String(baseurl))) => {
+                (String::new(), normalise_baseurl(baseurl))
+            }
+            (Some(toml::Value::String(url)), Some(toml::Value::String(baseurl))) => (
+                url.trim_end_matches('/').to_string(),
+                normalise_baseurl(baseurl),
+            ),
+            _ => {
+                return Err(CustomError::ConfigError(format!(
+                    "{:?} url and baseurl need to be strings e.g. \"https://example.com\" and \"/project\"",
+                    path
+                )))
+            }
+        };
+
+        let pretty_urls = match table.get("pretty_urls") {
+            Some(toml::Value::Boolean(pretty_urls)) => *pretty_urls,
+            Some(_) => {
+                return Err(CustomError::ConfigError(format!(
+                    "{:?} pretty_urls needs to be true or false",
+                    path
+                )))
+            }
+            None => false,
+        };
+
+        let variables =
+            match util::yaml_to_liquid(&toml_to_yaml(toml::Value::Table(table)), &timezone) {
+                liquid::model::Value::Object(variables) => variables,
+                _ => liquid::Object::new(),
+            };
+
+        Ok(SiteConfig {
+            root: PathBuf::new(),
+            build,
+            markdown,
+            highlight,
+            defaults,
+            collections,
+            timezone,
+            excerpt_separator,
+            permalink,
+            url,
+            baseurl,
+            pretty_urls,
+            variables,
+        })
+    }
+}
+
+fn normalise_baseurl(baseurl: &str) -> String {
+    let baseurl = baseurl.trim().trim_matches('/');
+    if baseurl.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", baseurl)
+    }
+}
+
+#[cfg(test)]
+mod site_tests {
+    use super::*;
+    use liquid::ValueView;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse_site_config() {
+        let site = SiteConfig::parse(
+            "title = \"mole\"\nauthor = { name = \"mole\" }\n\n[build]\ndest = \"public/\"\n",
+            Path::new("mole.toml"),
+        )
+        .unwrap();
+        assert_eq!(Some(PathBuf::from("public/")), site.build.dest);
+        assert_eq!(None, site.build.layouts);
+        assert_eq!(
+            "mole",
+            site.variables.get("title").unwrap().to_kstr().to_string()
+        );
+        assert!(site.variables.get("author").unwrap().as_object().is_some());
+        assert!(site.variables.get("build").is_none());
+        assert_eq!(EXCERPT_SEPARATOR, site.excerpt_separator);
+
+        let site = SiteConfig::parse(
+            "excerpt_separator = \"<!-- cut -->\"\n",
+            Path::new("mole.toml"),
+        )
+        .unwrap();
+        assert_eq!("<!-- cut -->", site.excerpt_separator);
+    }
+
+    #[test]
+    fn parse_markdown_config() {
+        let site = SiteConfig::parse(
+            "[markdown]\nfootnotes = false\nsmart_punctuation = true\n",
+            Path::new("mole.toml"),
+        )
+        .unwrap();
+        assert_eq!(
+            MarkdownConfig {
+                footnotes: false,
+                smart_punctuation: true,
+                ..MarkdownConfig::default()
+            },
+            site.markdown
+        );
+        assert!(site.variables.get("markdown").is_none());
+        assert!(SiteConfig::parse("[markdown]\nsmart = true\n", Path::new("mole.toml")).is_err());
+
+        let site = SiteConfig::parse(
+            "[markdown.admonition_classes]\nclass = \"alert\"\nwarning = \"alert-danger\"\n",
+            Path::new("mole.toml"),
+        )
+        .unwrap();
+        assert_eq!("alert", site.markdown.admonition_classes.class);
+        assert_eq!("alert-danger", site.markdown.admonition_classes.warning);
+        assert_eq!("callout-note", site.markdown.admonition_classes.note);
+        assert!(site.markdown.admonitions);
+    }
+
+    #[test]
+    fn parse_collections() {
+        let mut site = SiteConfig::parse(
+            "[collections.docs]\nlayout = \"doc\"\nsort_by = \"weight\"\n\n[collections.projects]\ndir = \"work/projects\"\nreverse = true\noutput = false\n",
+            Path::new("mole.toml"),
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                Collection {
+                    name: "docs".to_string(),
+                    dir: PathBuf::from("_docs"),
+                    layout: Some("doc".to_string()),
+                    permalink: None,
+                    sort_by: "weight".to_string(),
+                    reverse: false,
+                    output: true,
+                },
+                Collection {
+                    name: "projects".to_string(),
+                    dir: PathBuf::from("work/projects"),
+                    layout: None,
+                    permalink: None,
+                    sort_by: "date".to_string(),
+                    reverse: true,
+                    output: false,
+                },
+            ],
+            site.collections
+        );
+        assert!(site.variables.get("collections").is_none());
+
+        site.root = PathBuf::from("/site");
+        let docs = site.collection_for(Path::new("/site/_docs/setup/install.md"));
+        assert_eq!(Some("docs"), docs.map(|c| c.name.as_str()));
+        assert!(site
+            .collection_for(Path::new("/site/work/projects/mole.md"))
+            .is_some());
+        assert!(site
+            .collection_for(Path::new("/site/_articles/cats.md"))
+            .is_none());
+        assert!(site.collection("projects").is_some());
+
+        assert!(SiteConfig::parse(
+            "[collections.docs]\nlayouts = \"doc\"\n",
+            Path::new("mole.toml")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn defaults_scope() {
+        let mut site = SiteConfig::parse(
+            "[[defaults]]\n[defaults.values]\nlayout = \"page\"\n\n[[defaults]]\npath = \"_articles/docs\"\n[defaults.values]\nlayout = \"doc\"\n\n[[defaults]]\nglob = \"**/*.html\"\n",
+            Path::new("mole.toml"),
+        )
+        .unwrap();
+        site.root = PathBuf::from("site");
+
+        let rules = site.defaults_for(Path::new("site/_articles/docs/intro.md"));
+        assert_eq!(
+            vec![1, 0],
+            rules.iter().map(|r| r.index).collect::<Vec<_>>()
+        );
+
+        let rules = site.defaults_for(Path::new("site/_articles/post.md"));
+        assert_eq!(vec![0], rules.iter().map(|r| r.index).collect::<Vec<_>>());
+
+        let rules = site.defaults_for(Path::new("site/about.html"));
+        assert_eq!(
+            vec![2, 0],
+            rules.iter().map(|r| r.index).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn parse_site_config_unknown_build_key() {
+        let site = SiteConfig::parse(
+            "[build]\ndestination = \"public/\"\n",
+            Path::new("mole.toml"),
+        );
+        match site {
+            Err(CustomError::ConfigError(e)) => assert!(e.contains("destination"), "{}", e),
+            _ => assert!(false, "expected a config error"),
+        }
+    }
+}
@@ -0,0 +1,1092 @@
+//! liquid tags mole adds on top of the standard library
+use crate::site::SiteConfig;
+use crate::util::{escape_html, slugify};
+use liquid_core::error::ResultLiquidReplaceExt;
+use liquid_core::parser::{BlockReflection, Language, TagBlock, TagReflection, TagTokenIter};
+use liquid_core::runtime::{Renderable, Runtime, Template};
+use liquid_core::{Error, Object, ParseBlock, ParseTag, Result, Value};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs::read_to_string;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// adds mole's tags, used for both the build and the article tests
+pub fn register<P: liquid::partials::PartialCompiler>(
+    builder: liquid::ParserBuilder<P>,
+    site: &SiteConfig,
+) -> liquid::ParserBuilder<P> {
+    builder
+        .tag(IncludeCodeTag {
+            root: site.root.clone(),
+        })
+        .tag(FigureTag {
+            baseurl: site.baseurl.clone(),
+        })
+        .tag(VideoTag {
+            baseurl: site.baseurl.clone(),
+        })
+        .block(CalloutBlock {
+            name: "callout",
+            end: "endcallout",
+            kind: None,
+        })
+        .block(CalloutBlock {
+            name: "note",
+            end: "endnote",
+            kind: Some("note"),
+        })
+}
+
+/// liquid wants 'static tag names, they're leaked the first time they're seen so rebuilding
+/// with `--serve` doesn't leak them again
+static NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+fn intern(name: String) -> &'static str {
+    let mut names = NAMES.lock().unwrap();
+    if let Some(name) = names.get(name.as_str()).copied() {
+        return name;
+    }
+    let name: &'static str = Box::leak(name.into_boxed_str());
+    names.insert(name);
+    name
+}
+
+/// adds the templates from `_shortcodes/`, after `register` so they can replace the built in ones
+pub fn register_shortcodes<P: liquid::partials::PartialCompiler>(
+    mut builder: liquid::ParserBuilder<P>,
+    shortcodes: &[Shortcode],
+) -> liquid::ParserBuilder<P> {
+    for shortcode in shortcodes {
+        let name = intern(shortcode.name.clone());
+        if shortcode.is_block() {
+            builder = builder.block(ShortcodeBlock {
+                name,
+                end: intern(format!("end{}", name)),
+                shortcode: shortcode.clone(),
+            });
+        } else {
+            builder = builder.tag(ShortcodeTag {
+                name,
+                shortcode: shortcode.clone(),
+            });
+        }
+    }
+    builder
+}
+
+/// what liquid split the tag's arguments into
+fn tokens(arguments: TagTokenIter) -> Vec<String> {
+    arguments.map(|token| token.as_str().to_string()).collect()
+}
+
+/// `{% include_code "examples/hello.rs" lines="3-10" lang="rust" %}` or `region="main"` for the
+/// lines between `ANCHOR: main` and `ANCHOR_END: main` comments in the file
+#[derive(Debug, Clone, Default, PartialEq)]
+struct IncludeCode {
+    /// relative to the project root
+    path: PathBuf,
+    /// 1-based and inclusive, the end is None for "3-"
+    lines: Option<(usize, Option<usize>)>,
+    region: Option<String>,
+    lang: Option<String>,
+}
+
+impl IncludeCode {
+    /// the tokens are what liquid split the arguments into, `lines` `=` `"3-10"`
+    fn parse(tokens: &[&str]) -> std::result::Result<Self, String> {
+        let mut tokens = tokens.iter();
+        let path = match tokens.next() {
+            Some(path) if is_quoted(path) => PathBuf::from(unquote(path)),
+            Some(path) => {
+                return Err(format!(
+                    "include_code expected a quoted path found {:?}",
+                    path
+                ))
+            }
+            None => {
+                return Err(
+                    "include_code needs a path e.g. {% include_code \"examples/hello.rs\" %}"
+                        .to_string(),
+                )
+            }
+        };
+        let mut include = IncludeCode {
+            path,
+            ..IncludeCode::default()
+        };
+
+        while let Some(key) = tokens.next() {
+            let value = match (tokens.next(), tokens.next()) {
+                (Some(&"="), Some(value)) => unquote(value),
+                _ => {
+                    return Err(format!(
+                        "include_code expected {}=\"...\" after the path",
+                        key
+                    ))
+                }
+            };
+            match *key {
+                "lines" => include.lines = Some(parse_lines(value)?),
+                "region" => include.region = Some(value.to_string()),
+                "lang" => include.lang = Some(value.to_string()),
+                _ => {
+                    return Err(format!(
+                        "include_code unknown argument {:?}, expected lines, region or lang",
+                        key
+                    ))
+                }
+            }
+        }
+
+        if include.lines.is_some() && include.region.is_some() {
+            return Err("include_code can't use both lines and region".to_string());
+        }
+        Ok(include)
+    }
+
+    /// the part of `content` that was asked for
+    fn select(&self, content: &str) -> std::result::Result<String, String> {
+        let lines: Vec<&str> = content.lines().collect();
+        if let Some((start, end)) = self.lines {
+            let end = end.unwrap_or(lines.len());
+            if start == 0 || start > end || end > lines.len() {
+                return Err(format!(
+                    "{:?} lines {}-{} are out of range, it has {} lines",
+                    self.path,
+                    start,
+                    end,
+                    lines.len()
+                ));
+            }
+            return Ok(lines[start - 1..end].join("\n"));
+        }
+
+        if let Some(region) = &self.region {
+            let start_marker = format!("ANCHOR: {}", region);
+            let end_marker = format!("ANCHOR_END: {}", region);
+            let start = lines
+                .iter()
+                .position(|line| ends_with_marker(line, &start_marker))
+                .ok_or_else(|| format!("{:?} has no region {:?}", self.path, region))?;
+            let end = lines[start..]
+                .iter()
+                .position(|line| ends_with_marker(line, &end_marker))
+                .ok_or_else(|| {
+                    format!(
+                        "{:?} region {:?} is missing {:?}",
+                        self.path, region, end_marker
+                    )
+                })?;
+            // any other regions inside this one are left out
+            return Ok(lines[start + 1..start + end]
+                .iter()
+                .filter(|line| !line.contains("ANCHOR: ") && !line.contains("ANCHOR_END: "))
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("\n"));
+        }
+
+        Ok(content.trim_end().to_string())
+    }
+
+    /// `lang` or the file extension
+    fn lang(&self) -> String {
+        match &self.lang {
+            Some(lang) => lang.clone(),
+            None => self
+                .path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or("")
+                .to_string(),
+        }
+    }
+
+    fn render(&self, root: &Path) -> std::result::Result<String, String> {
+        // `..` and symlinks are resolved first so nothing outside the project can be included
+        let root = if root.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            root
+        };
+        let root = root
+            .canonicalize()
+            .map_err(|e| format!("include_code project root {:?} {}", root, e))?;
+        let path = root
+            .join(&self.path)
+            .canonicalize()
+            .map_err(|e| format!("include_code {:?} {}", root.join(&self.path), e))?;
+        if !path.starts_with(&root) {
+            return Err(format!(
+                "include_code {:?} is outside the project root {:?}",
+                self.path, root
+            ));
+        }
+        let content =
+            read_to_string(&path).map_err(|e| format!("include_code {:?} {}", path, e))?;
+        let code = self.select(&content)?;
+
+        // the fence needs to be longer than any run of backticks in the code
+        let mut fence = String::from("```");
+        while code.contains(&fence) {
+            fence.push('`');
+        }
+        // the article goes through liquid twice, raw stops the code from being read as liquid
+        Ok(format!(
+            "{{% raw %}}\n{fence}{lang}\n{code}\n{fence}\n{{% endraw %}}",
+            fence = fence,
+            lang = self.lang(),
+            code = code
+        ))
+    }
+}
+
+fn is_quoted(token: &str) -> bool {
+    token.len() >= 2
+        && ((token.starts_with('"') && token.ends_with('"'))
+            || (token.starts_with('\'') && token.ends_with('\'')))
+}
+
+fn unquote(token: &str) -> &str {
+    if is_quoted(token) {
+        &token[1..token.len() - 1]
+    } else {
+        token
+    }
+}
+
+/// "3-10", "3" or "3-"
+fn parse_lines(value: &str) -> std::result::Result<(usize, Option<usize>), String> {
+    let error = || {
+        format!(
+            "include_code lines should look like \"3-10\" found {:?}",
+            value
+        )
+    };
+    match value.find('-') {
+        Some(i) => {
+            let start = value[..i].trim().parse::<usize>().map_err(|_| error())?;
+            let end = value[i + 1..].trim();
+            if end.is_empty() {
+                Ok((start, None))
+            } else {
+                Ok((start, Some(end.parse::<usize>().map_err(|_| error())?)))
+            }
+        }
+        None => {
+            let line = value.trim().parse::<usize>().map_err(|_| error())?;
+            Ok((line, Some(line)))
+        }
+    }
+}
+
+/// the marker has to be the end of the line so `ANCHOR: main` doesn't match `ANCHOR: main_loop`
+fn ends_with_marker(line: &str, marker: &str) -> bool {
+    line.trim_end()
+        .trim_end_matches("*/")
+        .trim_end_matches("-->")
+        .trim_end()
+        .ends_with(marker)
+}
+
+#[derive(Debug, Clone)]
+struct IncludeCodeTag {
+    root: PathBuf,
+}
+
+impl TagReflection for IncludeCodeTag {
+    fn tag(&self) -> &'static str {
+        "include_code"
+    }
+
+    fn description(&self) -> &'static str {
+        "includes a file relative to the project root as a fenced code block"
+    }
+}
+
+impl ParseTag for IncludeCodeTag {
+    fn parse(&self, arguments: TagTokenIter, _options: &Language) -> Result<Box<dyn Renderable>> {
+        let tokens = tokens(arguments);
+        let tokens: Vec<&str> = tokens.iter().map(|token| token.as_str()).collect();
+        let include = IncludeCode::parse(&tokens).map_err(Error::with_msg)?;
+        Ok(Box::new(IncludeCodeRenderable {
+            root: self.root.clone(),
+            include,
+        }))
+    }
+
+    fn reflection(&self) -> &dyn TagReflection {
+        self
+    }
+}
+
+#[derive(Debug)]
+struct IncludeCodeRenderable {
+    root: PathBuf,
+    include: IncludeCode,
+}
+
+impl Renderable for IncludeCodeRenderable {
+    fn render_to(&self, writer: &mut dyn Write, _runtime: &mut Runtime) -> Result<()> {
+        let code = self.include.render(&self.root).map_err(Error::with_msg)?;
+        write!(writer, "{}", code).replace("Failed to render")?;
+        Ok(())
+    }
+}
+
+/// `"positional" key="value"` arguments, the values can't be variables
+#[derive(Debug, Default, PartialEq)]
+struct Arguments {
+    positional: Vec<String>,
+    keyword: Vec<(String, String)>,
+}
+
+impl Arguments {
+    fn parse(tag: &str, tokens: &[String]) -> std::result::Result<Self, String> {
+        let mut arguments = Arguments::default();
+        let mut tokens = tokens.iter().peekable();
+        while let Some(token) = tokens.next() {
+            if tokens.peek().map(|next| next.as_str()) == Some("=") {
+                tokens.next();
+                match tokens.next() {
+                    Some(value) => arguments
+                        .keyword
+                        .push((token.clone(), unquote(value).to_string())),
+                    None => return Err(format!("{} expected a value after {}=", tag, token)),
+                }
+            } else {
+                arguments.positional.push(unquote(token).to_string());
+            }
+        }
+        Ok(arguments)
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.keyword
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// errors on anything the tag doesn't take
+    fn check(
+        &self,
+        tag: &str,
+        positional: usize,
+        keys: &[&str],
+    ) -> std::result::Result<(), String> {
+        if self.positional.len() != positional {
+            return Err(format!(
+                "{} expects {} quoted arguments before the key=\"value\" ones, found {}",
+                tag,
+                positional,
+                self.positional.len()
+            ));
+        }
+        match self
+            .keyword
+            .iter()
+            .find(|(key, _)| !keys.contains(&key.as_str()))
+        {
+            Some((key, _)) => Err(format!(
+                "{} unknown argument {:?}, expected one of {}",
+                tag,
+                key,
+                keys.join(", ")
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// `shortcode.args` and `shortcode.<key>` in a shortcode template
+    fn to_liquid(&self) -> Object {
+        let mut object = Object::new();
+        object.insert(
+            "args".into(),
+            Value::Array(self.positional.iter().cloned().map(Value::scalar).collect()),
+        );
+        for (key, value) in &self.keyword {
+            object.insert(key.clone().into(), Value::scalar(value.clone()));
+        }
+        object
+    }
+}
+
+/// root relative urls get the baseurl like the `relative_url` filter
+fn site_url(baseurl: &str, url: &str) -> String {
+    if url.starts_with('/') && !url.starts_with("//") {
+        format!("{}{}", baseurl, url)
+    } else {
+        url.to_string()
+    }
+}
+
+/// `{% figure "/images/cat.png" caption="A cat" alt="..." class="wide" %}`, the caption can
+/// have html in it
+fn figure(arguments: &Arguments, baseurl: &str) -> std::result::Result<String, String> {
+    arguments.check("figure", 1, &["caption", "alt", "class"])?;
+    let src = site_url(baseurl, &arguments.positional[0]);
+    let mut html = match arguments.get("class") {
+        Some(class) => format!("<figure class=\"{}\">", escape_html(class)),
+        None => String::from("<figure>"),
+    };
+    html += &format!(
+        "<img src=\"{}\" alt=\"{}\">",
+        escape_html(&src),
+        escape_html(
+            arguments
+                .get("alt")
+                .or(arguments.get("caption"))
+                .unwrap_or("")
+        )
+    );
+    if let Some(caption) = arguments.get("caption") {
+        html += &format!("<figcaption>{}</figcaption>", caption);
+    }
+    html += "</figure>";
+    Ok(html)
+}
+
+/// the embed url for youtube and vimeo links
+fn embed_url(url: &str) -> Option<String> {
+    let rest = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.")
+        .trim_start_matches("m.");
+    let id = |s: &str| {
+        s.split(&['?', '&', '#', '/'][..])
+            .next()
+            .filter(|id| !id.is_empty())
+            .map(|id| id.to_string())
+    };
+
+    let youtube = if let Some(query) = rest.strip_prefix("youtube.com/watch?") {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("v="))
+            .and_then(id)
+    } else if let Some(path) = rest.strip_prefix("youtu.be/") {
+        id(path)
+    } else if let Some(path) = rest
+        .strip_prefix("youtube.com/embed/")
+        .or_else(|| rest.strip_prefix("youtube.com/shorts/"))
+    {
+        id(path)
+    } else {
+        None
+    };
+    if let Some(youtube) = youtube {
+        return Some(format!(
+            "https://www.youtube-nocookie.com/embed/{}",
+            youtube
+        ));
+    }
+
+    rest.strip_prefix("vimeo.com/")
+        .and_then(id)
+        .filter(|id| id.chars().all(|c| c.is_ascii_digit()))
+        .map(|vimeo| format!("https://player.vimeo.com/video/{}", vimeo))
+}
+
+/// `{% video "https://www.youtube.com/watch?v=..." title="..." %}` for youtube and vimeo, any
+/// other url is a `<video>` with `poster="/images/poster.png"` as an option
+fn video(arguments: &Arguments, baseurl: &str) -> std::result::Result<String, String> {
+    arguments.check("video", 1, &["title", "poster"])?;
+    let url = &arguments.positional[0];
+    let title = escape_html(arguments.get("title").unwrap_or(""));
+
+    let player = match embed_url(url) {
+        Some(embed) => format!(
+            "<iframe src=\"{}\" title=\"{}\" frameborder=\"0\" allow=\"accelerometer; autoplay; clipboard-write; encrypted-media; gyroscope; picture-in-picture\" allowfullscreen></iframe>",
+            embed, title
+        ),
+        None => {
+            let poster = match arguments.get("poster") {
+                Some(poster) => format!(
+                    " poster=\"{}\"",
+                    escape_html(&site_url(baseurl, poster))
+                ),
+                None => String::new(),
+            };
+            format!(
+                "<video src=\"{}\" title=\"{}\"{} controls></video>",
+                escape_html(&site_url(baseurl, url)),
+                title,
+                poster
+            )
+        }
+    };
+    Ok(format!("<div class=\"video\">{}</div>", player))
+}
+
+/// the start of `{% callout type="warning" title="Careful" %}`, the title defaults to the type.
+/// The blank lines around the content let markdown inside it still be rendered
+fn callout_start(arguments: &Arguments, kind: Option<&str>) -> std::result::Result<String, String> {
+    let kind = match kind {
+        Some(kind) => {
+            arguments.check(kind, 0, &["title"])?;
+            kind.to_string()
+        }
+        None => {
+            arguments.check("callout", 0, &["type", "title"])?;
+            arguments.get("type").unwrap_or("note").to_string()
+        }
+    };
+    let title = match arguments.get("title") {
+        Some(title) => title.to_string(),
+        None => {
+            let mut chars = kind.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        }
+    };
+
+    let mut html = format!("<div class=\"callout callout-{}\">\n", slugify(&kind));
+    if !title.is_empty() {
+        html += &format!("<p class=\"callout-title\">{}</p>\n", title);
+    }
+    html += "\n";
+    Ok(html)
+}
+
+/// output that's known once the tag is parsed
+#[derive(Debug)]
+struct Html(String);
+
+impl Renderable for Html {
+    fn render_to(&self, writer: &mut dyn Write, _runtime: &mut Runtime) -> Result<()> {
+        write!(writer, "{}", self.0).replace("Failed to render")?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FigureTag {
+    baseurl: String,
+}
+
+impl TagReflection for FigureTag {
+    fn tag(&self) -> &'static str {
+        "figure"
+    }
+
+    fn description(&self) -> &'static str {
+        "an image with a caption"
+    }
+}
+
+impl ParseTag for FigureTag {
+    fn parse(&self, arguments: TagTokenIter, _options: &Language) -> Result<Box<dyn Renderable>> {
+        let arguments = Arguments::parse("figure", &tokens(arguments)).map_err(Error::with_msg)?;
+        let html = figure(&arguments, &self.baseurl).map_err(Error::with_msg)?;
+        Ok(Box::new(Html(html)))
+    }
+
+    fn reflection(&self) -> &dyn TagReflection {
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+struct VideoTag {
+    baseurl: String,
+}
+
+impl TagReflection for VideoTag {
+    fn tag(&self) -> &'static str {
+        "video"
+    }
+
+    fn description(&self) -> &'static str {
+        "a youtube or vimeo embed or a self hosted video"
+    }
+}
+
+impl ParseTag for VideoTag {
+    fn parse(&self, arguments: TagTokenIter, _options: &Language) -> Result<Box<dyn Renderable>> {
+        let arguments = Arguments::parse("video", &tokens(arguments)).map_err(Error::with_msg)?;
+        let html = video(&arguments, &self.baseurl).map_err(Error::with_msg)?;
+        Ok(Box::new(Html(html)))
+    }
+
+    fn reflection(&self) -> &dyn TagReflection {
+        self
+    }
+}
+
+/// `callout` takes a `type`, `note` is a callout that's always a note
+#[derive(Debug, Clone)]
+struct CalloutBlock {
+    name: &'static str,
+    end: &'static str,
+    kind: Option<&'static str>,
+}
+
+impl BlockReflection for CalloutBlock {
+    fn start_tag(&self) -> &'static str {
+        self.name
+    }
+
+    fn end_tag(&self) -> &'static str {
+        self.end
+    }
+
+    fn description(&self) -> &'static str {
+        "a box around a warning, tip or note"
+    }
+}
+
+impl ParseBlock for CalloutBlock {
+    fn parse(
+        &self,
+        arguments: TagTokenIter,
+        mut block: TagBlock,
+        options: &Language,
+    ) -> Result<Box<dyn Renderable>> {
+        let arguments = Arguments::parse(self.name, &tokens(arguments)).map_err(Error::with_msg)?;
+        let start = callout_start(&arguments, self.kind).map_err(Error::with_msg)?;
+        let content = Template::new(block.parse_all(options)?);
+        block.assert_empty();
+        Ok(Box::new(Callout { start, content }))
+    }
+
+    fn reflection(&self) -> &dyn BlockReflection {
+        self
+    }
+}
+
+#[derive(Debug)]
+struct Callout {
+    start: String,
+    content: Template,
+}
+
+impl Renderable for Callout {
+    fn render_to(&self, writer: &mut dyn Write, runtime: &mut Runtime) -> Result<()> {
+        let content = render_content(&self.content, runtime)?;
+        write!(writer, "{}{}\n\n</div>", self.start, content.trim()).replace("Failed to render")?;
+        Ok(())
+    }
+}
+
+fn render_content(content: &Template, runtime: &mut Runtime) -> Result<String> {
+    let mut output = Vec::new();
+    content.render_to(&mut output, runtime)?;
+    Ok(String::from_utf8_lossy(&output).to_string())
+}
+
+/// a template from `_shortcodes/`, `_shortcodes/youtube.html` is `{% youtube "id" %}`. One
+/// that uses `shortcode.content` is a block, `{% aside %}...{% endaside %}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shortcode {
+    pub name: String,
+    pub source: String,
+}
+
+impl Shortcode {
+    fn is_block(&self) -> bool {
+        self.source.contains("shortcode.content")
+    }
+
+    /// parses the template each time it's used, the arguments are fixed so it's only the
+    /// template that's shared
+    fn template(&self, options: &Language) -> Result<Template> {
+        // a shortcode that ends up using itself, directly or through others, would never
+        // finish parsing
+        let cycle = PARSING.with(|parsing| {
+            let mut parsing = parsing.borrow_mut();
+            match parsing.iter().position(|name| *name == self.name) {
+                Some(start) => Some(parsing[start..].join(" -> ")),
+                None => {
+                    parsing.push(self.name.clone());
+                    None
+                }
+            }
+        });
+        if let Some(cycle) = cycle {
+            return Err(Error::with_msg(format!(
+                "shortcode {:?} uses itself through {} -> {}",
+                self.name, cycle, self.name
+            )));
+        }
+        let template = liquid_core::parser::parse(&self.source, options).map(Template::new);
+        PARSING.with(|parsing| parsing.borrow_mut().pop());
+        template
+    }
+}
+
+thread_local! {
+    /// the shortcodes whose templates are being parsed, outermost first
+    static PARSING: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Debug, Clone)]
+struct ShortcodeTag {
+    name: &'static str,
+    shortcode: Shortcode,
+}
+
+impl TagReflection for ShortcodeTag {
+    fn tag(&self) -> &'static str {
+        self.name
+    }
+
+    fn description(&self) -> &'static str {
+        "a template from _shortcodes/"
+    }
+}
+
+impl ParseTag for ShortcodeTag {
+    fn parse(&self, arguments: TagTokenIter, options: &Language) -> Result<Box<dyn Renderable>> {
+        let arguments = Arguments::parse(self.name, &tokens(arguments)).map_err(Error::with_msg)?;
+        Ok(Box::new(ShortcodeRenderable {
+            template: self.shortcode.template(options)?,
+            arguments: arguments.to_liquid(),
+            content: None,
+        }))
+    }
+
+    fn reflection(&self) -> &dyn TagReflection {
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ShortcodeBlock {
+    name: &'static str,
+    end: &'static str,
+    shortcode: Shortcode,
+}
+
+impl BlockReflection for ShortcodeBlock {
+    fn start_tag(&self) -> &'static str {
+        self.name
+    }
+
+    fn end_tag(&self) -> &'static str {
+        self.end
+    }
+
+    fn description(&self) -> &'static str {
+        "a template from _shortcodes/ that wraps its content"
+    }
+}
+
+impl ParseBlock for ShortcodeBlock {
+    fn parse(
+        &self,
+        arguments: TagTokenIter,
+        mut block: TagBlock,
+        options: &Language,
+    ) -> Result<Box<dyn Renderable>> {
+        let arguments = Arguments::parse(self.name, &tokens(arguments)).map_err(Error::with_msg)?;
+        let content = Template::new(block.parse_all(options)?);
+        block.assert_empty();
+        Ok(Box::new(ShortcodeRenderable {
+            template: self.shortcode.template(options)?,
+            arguments: arguments.to_liquid(),
+            content: Some(content),
+        }))
+    }
+
+    fn reflection(&self) -> &dyn BlockReflection {
+        self
+    }
+}
+
+#[derive(Debug)]
+struct ShortcodeRenderable {
+    template: Template,
+    arguments: Object,
+    content: Option<Template>,
+}
+
+impl Renderable for ShortcodeRenderable {
+    fn render_to(&self, writer: &mut dyn Write, runtime: &mut Runtime) -> Result<()> {
+        let mut arguments = self.arguments.clone();
+        if let Some(content) = &self.content {
+            let content = render_content(content, runtime)?;
+            arguments.insert("content".into(), Value::scalar(content.trim().to_string()));
+        }
+        // the template sees the page's variables too
+        runtime.run_in_named_scope("shortcode", |scope| {
+            scope.stack_mut().set("shortcode", Value::Object(arguments));
+            self.template.render_to(writer, scope)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tag_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const CODE: &str = "use std::io;\n\n// ANCHOR: main\nfn main() {\n    // ANCHOR: print\n    println!(\"{{cats}}\");\n    // ANCHOR_END: print\n}\n// ANCHOR_END: main\n";
+
+    #[test]
+    fn parse_arguments() {
+        assert_eq!(
+            IncludeCode {
+                path: PathBuf::from("examples/hello.rs"),
+                lines: Some((3, Some(10))),
+                region: None,
+                lang: Some("rust".to_string()),
+            },
+            IncludeCode::parse(&[
+                "\"examples/hello.rs\"",
+                "lines",
+                "=",
+                "\"3-10\"",
+                "lang",
+                "=",
+                "'rust'"
+            ])
+            .unwrap()
+        );
+        assert_eq!(
+            Some((3, Some(3))),
+            IncludeCode::parse(&["\"a.rs\"", "lines", "=", "3"])
+                .unwrap()
+                .lines
+        );
+        assert!(IncludeCode::parse(&[]).is_err());
+        assert!(IncludeCode::parse(&["a.rs"]).is_err());
+        assert!(IncludeCode::parse(&["\"a.rs\"", "cats", "=", "\"1\""]).is_err());
+        assert!(IncludeCode::parse(&["\"a.rs\"", "lines", "=", "\"a-b\""]).is_err());
+        assert!(IncludeCode::parse(&[
+            "\"a.rs\"", "lines", "=", "\"1\"", "region", "=", "\"main\""
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn select_lines() {
+        let mut include = IncludeCode::parse(&["\"a.rs\"", "lines", "=", "\"4-\""]).unwrap();
+        assert_eq!(
+            "fn main() {\n    // ANCHOR: print\n    println!(\"{{cats}}\");\n    // ANCHOR_END: print\n}\n// ANCHOR_END: main",
+            include.select(CODE).unwrap()
+        );
+        include.lines = Some((1, Some(20)));
+        assert!(include.select(CODE).is_err());
+    }
+
+    #[test]
+    fn select_region() {
+        let mut include = IncludeCode::parse(&["\"a.rs\"", "region", "=", "\"main\""]).unwrap();
+        assert_eq!(
+            "fn main() {\n    println!(\"{{cats}}\");\n}",
+            include.select(CODE).unwrap()
+        );
+        include.region = Some("mai".to_string());
+        assert!(include.select(CODE).is_err());
+    }
+
+    #[test]
+    fn render_tag() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("examples")).unwrap();
+        std::fs::write(dir.path().join("examples/hello.rs"), CODE).unwrap();
+        let site = SiteConfig {
+            root: dir.path().to_path_buf(),
+            ..SiteConfig::default()
+        };
+        let parser = register(liquid::ParserBuilder::with_stdlib(), &site)
+            .build()
+            .unwrap();
+
+        // twice like Article::pre_render does
+        let once = parser
+            .parse("{% include_code \"examples/hello.rs\" region=\"print\" %}")
+            .unwrap()
+            .render(&liquid::object!({}))
+            .unwrap();
+        assert_eq!(
+            "\n```rs\n    println!(\"{{cats}}\");\n```\n",
+            parser
+                .parse(&once)
+                .unwrap()
+                .render(&liquid::object!({}))
+                .unwrap()
+        );
+
+        assert!(parser
+            .parse("{% include_code \"examples/missing.rs\" %}")
+            .unwrap()
+            .render(&liquid::object!({}))
+            .is_err());
+    }
+
+    #[test]
+    fn include_outside_root() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("site")).unwrap();
+        std::fs::write(dir.path().join("secret.rs"), CODE).unwrap();
+        let site = SiteConfig {
+            root: dir.path().join("site"),
+            ..SiteConfig::default()
+        };
+        let parser = register(liquid::ParserBuilder::with_stdlib(), &site)
+            .build()
+            .unwrap();
+
+        match parser
+            .parse("{% include_code \"../secret.rs\" %}")
+            .unwrap()
+            .render(&liquid::object!({}))
+        {
+            Err(e) => assert!(e.to_string().contains("outside the project root"), "{}", e),
+            Ok(output) => assert!(false, "included {:?}", output),
+        }
+    }
+
+    fn render(template: &str, site: &SiteConfig, shortcodes: &[Shortcode]) -> Result<String> {
+        register_shortcodes(
+            register(liquid::ParserBuilder::with_stdlib(), site),
+            shortcodes,
+        )
+        .build()
+        .unwrap()
+        .parse(template)?
+        .render(&liquid::object!({ "page": { "title": "Cats" } }))
+    }
+
+    #[test]
+    fn shortcode_arguments() {
+        let tokens: Vec<String> = vec!["\"a.png\"", "caption", "=", "'A cat'", "wide"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let arguments = Arguments::parse("figure", &tokens).unwrap();
+        assert_eq!(
+            Arguments {
+                positional: vec!["a.png".to_string(), "wide".to_string()],
+                keyword: vec![("caption".to_string(), "A cat".to_string())],
+            },
+            arguments
+        );
+        assert_eq!(Some("A cat"), arguments.get("caption"));
+        assert!(arguments.check("figure", 2, &["caption"]).is_ok());
+        assert!(arguments.check("figure", 1, &["caption"]).is_err());
+        assert!(arguments.check("figure", 2, &["alt"]).is_err());
+        assert!(Arguments::parse("figure", &["caption".to_string(), "=".to_string()]).is_err());
+    }
+
+    #[test]
+    fn figure_and_video() {
+        let site = SiteConfig {
+            baseurl: "/mole".to_string(),
+            ..SiteConfig::default()
+        };
+        assert_eq!(
+            "<figure class=\"wide\"><img src=\"/mole/cat.png\" alt=\"A &quot;cat&quot;\"><figcaption>A <em>cat</em></figcaption></figure>",
+            render(
+                "{% figure \"/cat.png\" caption=\"A <em>cat</em>\" alt='A \"cat\"' class=\"wide\" %}",
+                &site,
+                &[]
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            Some("https://www.youtube-nocookie.com/embed/abc_123".to_string()),
+            embed_url("https://www.youtube.com/watch?feature=share&v=abc_123&t=10")
+        );
+        assert_eq!(
+            Some("https://www.youtube-nocookie.com/embed/abc_123".to_string()),
+            embed_url("https://youtu.be/abc_123?t=10")
+        );
+        assert_eq!(
+            Some("https://player.vimeo.com/video/42".to_string()),
+            embed_url("https://vimeo.com/42")
+        );
+        assert_eq!(None, embed_url("/videos/cat.mp4"));
+        assert_eq!(
+            "<div class=\"video\"><video src=\"/mole/cat.mp4\" title=\"\" poster=\"/mole/cat.png\" controls></video></div>",
+            render(
+                "{% video \"/cat.mp4\" poster=\"/cat.png\" %}",
+                &site,
+                &[]
+            )
+            .unwrap()
+        );
+        assert!(render("{% figure %}", &site, &[]).is_err());
+        assert!(render("{% video \"a.mp4\" width=\"3\" %}", &site, &[]).is_err());
+    }
+
+    #[test]
+    fn callouts() {
+        let site = SiteConfig::default();
+        assert_eq!(
+            "<div class=\"callout callout-warning\">\n<p class=\"callout-title\">Warning</p>\n\n**Cats** bite\n\n</div>",
+            render(
+                "{% callout type=\"warning\" %}\n**{{ page.title }}** bite\n{% endcallout %}",
+                &site,
+                &[]
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            "<div class=\"callout callout-note\">\n\ncats\n\n</div>",
+            render("{% note title=\"\" %}cats{% endnote %}", &site, &[]).unwrap()
+        );
+    }
+
+    #[test]
+    fn custom_shortcodes() {
+        let site = SiteConfig::default();
+        let shortcodes = vec![
+            Shortcode {
+                name: "greet".to_string(),
+                source:
+                    "hello {{ shortcode.args[0] }} from {{ shortcode.who }} on {{ page.title }}"
+                        .to_string(),
+            },
+            Shortcode {
+                name: "aside".to_string(),
+                source: "<aside>{{ shortcode.content }}</aside>".to_string(),
+            },
+            Shortcode {
+                name: "forever".to_string(),
+                source: "{% forever %}".to_string(),
+            },
+            Shortcode {
+                name: "ping".to_string(),
+                source: "ping {% pong %}".to_string(),
+            },
+            Shortcode {
+                name: "pong".to_string(),
+                source: "pong {% ping %}".to_string(),
+            },
+        ];
+        assert_eq!(
+            "hello mole from cats on Cats <aside>hello mole from dogs on Cats</aside>",
+            render(
+                "{% greet \"mole\" who=\"cats\" %} {% aside %} {% greet \"mole\" who=\"dogs\" %} {% endaside %}",
+                &site,
+                &shortcodes
+            )
+            .unwrap()
+        );
+        assert!(render("{% forever %}", &site, &shortcodes).is_err());
+        let error = render("{% ping %}", &site, &shortcodes).unwrap_err();
+        assert!(
+            error.to_string().contains("ping -> pong -> ping"),
+            "{}",
+            error
+        );
+        // rebuilding reuses the names leaked the first time
+        assert!(std::ptr::eq(
+            intern("greet".to_string()),
+            intern("greet".to_string())
+        ));
+        // the failed parse doesn't stop the shortcodes being used again
+        assert_eq!(
+            "<aside></aside>",
+            render("{% aside %}{% endaside %}", &site, &shortcodes).unwrap()
+        );
+    }
+}
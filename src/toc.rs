@@ -0,0 +1,171 @@
+//! heading anchors and the table of contents built from them
+use crate::util::{escape_html, slugify};
+use pulldown_cmark::{Event, Tag};
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// `page.toc`, `children` are the headings with a bigger level up until the next heading
+/// with the same or smaller level
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Heading {
+    pub level: u32,
+    pub text: String,
+    pub id: String,
+    pub children: Vec<Heading>,
+}
+
+/// gives every heading an id made from its text, repeats get "-1", "-2" on the end like github.
+/// Returns the headings in the order they appear
+pub fn anchor_headings<'a>(events: Vec<Event<'a>>) -> (Vec<Event<'a>>, Vec<Heading>) {
+    let mut output = Vec::with_capacity(events.len());
+    let mut headings = Vec::new();
+    let mut ids = HashSet::new();
+    // the events inside the heading currently being read
+    let mut heading: Option<(u32, Vec<Event<'a>>)> = None;
+
+    for event in events {
+        match (event, heading.as_mut()) {
+            (Event::Start(Tag::Heading(level)), None) => heading = Some((level, Vec::new())),
+            (Event::End(Tag::Heading(_)), Some(_)) => {
+                if let Some((level, inner)) = heading.take() {
+                    let text = inner
+                        .iter()
+                        .filter_map(|event| match event {
+                            Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                            _ => None,
+                        })
+                        .collect::<String>();
+                    let id = unique_id(&text, &mut ids);
+
+                    output.push(Event::Html(format!("<h{} id=\"{}\">", level, id).into()));
+                    output.extend(inner);
+                    output.push(Event::Html(format!("</h{}>\n", level).into()));
+                    headings.push(Heading {
+                        level,
+                        text,
+                        id,
+                        children: Vec::new(),
+                    });
+                }
+            }
+            (event, Some((_, inner))) => inner.push(event),
+            (event, None) => output.push(event),
+        }
+    }
+    (output, headings)
+}
+
+fn unique_id(text: &str, ids: &mut HashSet<String>) -> String {
+    let mut base = slugify(text);
+    if base.is_empty() {
+        base = String::from("section");
+    }
+    let mut id = base.clone();
+    let mut n = 1;
+    while ids.contains(&id) {
+        id = format!("{}-{}", base, n);
+        n += 1;
+    }
+    ids.insert(id.clone());
+    id
+}
+
+/// nests the flat list from `anchor_headings`
+pub fn tree(headings: Vec<Heading>) -> Vec<Heading> {
+    let mut roots: Vec<Heading> = Vec::new();
+    for heading in headings {
+        insert(&mut roots, heading);
+    }
+    roots
+}
+
+fn insert(siblings: &mut Vec<Heading>, heading: Heading) {
+    match siblings.last_mut() {
+        Some(last) if last.level < heading.level => insert(&mut last.children, heading),
+        _ => siblings.push(heading),
+    }
+}
+
+/// `page.toc_html`, `<nav class="toc"><ul><li><a href="#id">text</a><ul>...</ul></li></ul></nav>`
+pub fn to_html(tree: &[Heading]) -> String {
+    if tree.is_empty() {
+        return String::new();
+    }
+    format!("<nav class=\"toc\">{}</nav>\n", list_html(tree))
+}
+
+fn list_html(headings: &[Heading]) -> String {
+    let mut html = String::from("<ul>");
+    for heading in headings {
+        html += &format!(
+            "<li><a href=\"#{}\">{}</a>",
+            heading.id,
+            escape_html(&heading.text)
+        );
+        if !heading.children.is_empty() {
+            html += &list_html(&heading.children);
+        }
+        html += "</li>";
+    }
+    html += "</ul>";
+    html
+}
+
+#[cfg(test)]
+mod toc_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::{html, Parser};
+
+    fn heading(level: u32, text: &str, id: &str, children: Vec<Heading>) -> Heading {
+        Heading {
+            level,
+            text: text.to_string(),
+            id: id.to_string(),
+            children,
+        }
+    }
+
+    #[test]
+    fn anchors() {
+        let (events, headings) =
+            anchor_headings(Parser::new("# Cats & `Dogs`\n## Cats\n## Cats\n#\n").collect());
+        let mut output = String::new();
+        html::push_html(&mut output, events.into_iter());
+        assert_eq!(
+            "<h1 id=\"cats-dogs\">Cats &amp; <code>Dogs</code></h1>\n<h2 id=\"cats\">Cats</h2>\n<h2 id=\"cats-1\">Cats</h2>\n<h1 id=\"section\"></h1>\n",
+            output
+        );
+        assert_eq!(
+            vec!["cats-dogs", "cats", "cats-1", "section"],
+            headings.iter().map(|h| h.id.as_str()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn nesting() {
+        let (_, headings) =
+            anchor_headings(Parser::new("## a\n### b\n#### c\n### d\n## e\n# f\n").collect());
+        let tree = tree(headings);
+        assert_eq!(
+            vec![
+                heading(
+                    2,
+                    "a",
+                    "a",
+                    vec![
+                        heading(3, "b", "b", vec![heading(4, "c", "c", vec![])]),
+                        heading(3, "d", "d", vec![])
+                    ]
+                ),
+                heading(2, "e", "e", vec![]),
+                heading(1, "f", "f", vec![]),
+            ],
+            tree
+        );
+        assert_eq!(
+            "<nav class=\"toc\"><ul><li><a href=\"#a\">a</a><ul><li><a href=\"#b\">b</a><ul><li><a href=\"#c\">c</a></li></ul></li><li><a href=\"#d\">d</a></li></ul></li><li><a href=\"#e\">e</a></li><li><a href=\"#f\">f</a></li></ul></nav>\n",
+            to_html(&tree)
+        );
+    }
+}
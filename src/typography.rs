@@ -0,0 +1,134 @@
+//! smart punctuation, pulldown-cmark 0.7 doesn't have it so it's a pass over the events
+use pulldown_cmark::{Event, Tag};
+
+/// curly quotes, `--` to –, `---` to — and `...` to …, code spans, code blocks and anything
+/// inside `<code>`, `<pre>` or `<kbd>` html is left alone
+pub fn smart_punctuation(events: Vec<Event>) -> Vec<Event> {
+    // the character before the current text, None at the start of a block
+    let mut prev: Option<char> = None;
+    let mut in_code_block = false;
+    let mut in_code_html = 0;
+
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                in_code_block = true;
+                Event::Start(Tag::CodeBlock(kind))
+            }
+            Event::End(Tag::CodeBlock(kind)) => {
+                in_code_block = false;
+                prev = None;
+                Event::End(Tag::CodeBlock(kind))
+            }
+            Event::Text(text) if !in_code_block && in_code_html == 0 => {
+                Event::Text(smarten(&text, &mut prev).into())
+            }
+            Event::Text(text) => {
+                prev = text.chars().last().or(prev);
+                Event::Text(text)
+            }
+            Event::Code(code) => {
+                prev = code.chars().last().or(prev);
+                Event::Code(code)
+            }
+            Event::Html(html) => {
+                // a block of html can open and close a tag in one go
+                let lower = html.to_ascii_lowercase();
+                for tag in &["code", "pre", "kbd"] {
+                    let opened = lower.matches(&format!("<{}", tag)).count();
+                    let closed = lower.matches(&format!("</{}", tag)).count();
+                    in_code_html = (in_code_html + opened).saturating_sub(closed);
+                }
+                Event::Html(html)
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                prev = Some(' ');
+                event
+            }
+            // emphasis, links and the like don't change what comes before
+            Event::Start(Tag::Emphasis)
+            | Event::Start(Tag::Strong)
+            | Event::Start(Tag::Strikethrough)
+            | Event::Start(Tag::Link(..))
+            | Event::Start(Tag::Image(..))
+            | Event::End(Tag::Emphasis)
+            | Event::End(Tag::Strong)
+            | Event::End(Tag::Strikethrough)
+            | Event::End(Tag::Link(..))
+            | Event::End(Tag::Image(..)) => event,
+            _ => {
+                prev = None;
+                event
+            }
+        })
+        .collect()
+}
+
+fn smarten(text: &str, prev: &mut Option<char>) -> String {
+    let mut output = String::with_capacity(text.len());
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let (replacement, width) = match c {
+            '-' if chars.get(i + 1) == Some(&'-') && chars.get(i + 2) == Some(&'-') => ('—', 3),
+            '-' if chars.get(i + 1) == Some(&'-') => ('–', 2),
+            '.' if chars.get(i + 1) == Some(&'.') && chars.get(i + 2) == Some(&'.') => ('…', 3),
+            '"' if opens(*prev) => ('“', 1),
+            '"' => ('”', 1),
+            '\'' if opens(*prev) => ('‘', 1),
+            '\'' => ('’', 1),
+            _ => (c, 1),
+        };
+        output.push(replacement);
+        *prev = Some(replacement);
+        i += width;
+    }
+    output
+}
+
+/// a quote opens at the start of a block, after a space or after opening punctuation
+fn opens(prev: Option<char>) -> bool {
+    match prev {
+        None => true,
+        Some(c) => c.is_whitespace() || "([{–—“‘/".contains(c),
+    }
+}
+
+#[cfg(test)]
+mod typography_tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use pulldown_cmark::{html, Parser};
+
+    fn render(md: &str) -> String {
+        let mut output = String::new();
+        html::push_html(
+            &mut output,
+            smart_punctuation(Parser::new(md).collect()).into_iter(),
+        );
+        output
+    }
+
+    #[test]
+    fn punctuation() {
+        assert_eq!(
+            "<p>“Cats” aren’t ‘dogs’ – 1–2 — wait…</p>\n",
+            render("\"Cats\" aren't 'dogs' -- 1--2 --- wait...")
+        );
+        assert_eq!(
+            "<p>“<em>cats</em>” and (“dogs”)\n“mice”</p>\n",
+            render("\"*cats*\" and (\"dogs\")\n\"mice\"")
+        );
+        assert_eq!("<p><code>x</code>’s</p>\n", render("`x`'s"));
+    }
+
+    #[test]
+    fn code_is_left_alone() {
+        assert_eq!(
+            "<p>“a” <code>&quot;b&quot; -- c...</code> <code>'d'</code></p>\n<pre><code>&quot;e&quot; --\n</code></pre>\n",
+            render("\"a\" <code>\"b\" -- c...</code> `'d'`\n\n```\n\"e\" --\n```")
+        );
+    }
+}
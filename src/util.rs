@@ -1,4 +1,7 @@
-use std::fs::read_to_string;
+use crate::date::{parse_date, Timezone};
+use chrono::{DateTime, FixedOffset};
+use serde_yaml::Value;
+use std::fs::{create_dir_all, read_to_string, write};
 use std::path::{Path, PathBuf};
 
 use crate::error::CustomError;
@@ -25,6 +28,22 @@ pub fn search_dir(path: &PathBuf, file_type: &str, underscore: bool) -> Vec<Path
     f
 }
 
+/// search_dir but also goes into any sub directories that don't start with '_' or '.'
+pub fn walk_dir(path: &PathBuf, file_type: &str) -> Vec<PathBuf> {
+    let mut f = search_dir(path, file_type, true);
+    for entry in path.read_dir().expect("read_dir call failed").flatten() {
+        let sub = entry.path();
+        if sub.is_dir() {
+            let name = path_file_name_to_string(&sub).expect("Could not decode file name");
+            if !name.starts_with('_') && !name.starts_with('.') {
+                f.append(&mut walk_dir(&sub, file_type));
+            }
+        }
+    }
+
+    f
+}
+
 pub fn read_file(path: &Path) -> Result<String, CustomError> {
     match read_to_string(path)?.parse::<String>() {
         Ok(c) => Ok(c),
@@ -32,6 +51,14 @@ pub fn read_file(path: &Path) -> Result<String, CustomError> {
     }
 }
 
+/// creates any missing parent directories first
+pub fn write_file(path: &Path, content: &str) -> Result<(), CustomError> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).map_err(|e| CustomError::IOError(format!("{:?} {}", parent, e)))?;
+    }
+    write(path, content).map_err(|e| CustomError::IOError(format!("{:?} {}", path, e)))
+}
+
 /// note: should only be used for .html files
 pub fn path_file_name_to_string(file_path: &Path) -> Result<String, CustomError> {
     Ok(file_path
@@ -48,3 +75,67 @@ pub fn path_file_name_to_string(file_path: &Path) -> Result<String, CustomError>
         .to_owned()
         .replace(".html", ""))
 }
+
+/// yaml values to liquid ones, strings that look like dates become liquid dates
+/// so that `{{ page.config.updated | date: "%Y" }}` works the same as it would for `date`
+pub fn yaml_to_liquid(value: &Value, timezone: &Timezone) -> liquid::model::Value {
+    match value {
+        Value::Null => liquid::model::Value::Nil,
+        Value::Bool(b) => liquid::model::Value::scalar(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => liquid::model::Value::scalar(i),
+            None => liquid::model::Value::scalar(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => match parse_date(s, timezone) {
+            Ok(date) => to_liquid_date(&date),
+            Err(_) => liquid::model::Value::scalar(s.clone()),
+        },
+        Value::Sequence(items) => liquid::model::Value::Array(
+            items
+                .iter()
+                .map(|item| yaml_to_liquid(item, timezone))
+                .collect(),
+        ),
+        Value::Mapping(map) => {
+            let mut object = liquid::Object::new();
+            for (key, value) in map {
+                if let Some(key) = key.as_str() {
+                    object.insert(key.to_string().into(), yaml_to_liquid(value, timezone));
+                }
+            }
+            liquid::model::Value::Object(object)
+        }
+    }
+}
+
+/// keeps the offset so templates can format the date exactly as it was written
+pub fn to_liquid_date(date: &DateTime<FixedOffset>) -> liquid::model::Value {
+    match liquid::model::scalar::DateTime::from_str(
+        &date.format("%Y-%m-%d %H:%M:%S %z").to_string(),
+    ) {
+        Some(date) => liquid::model::Value::scalar(date),
+        None => liquid::model::Value::scalar(date.to_rfc3339()),
+    }
+}
+
+/// safe to put in html text or a quoted attribute
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// lowercase ascii letters and numbers with everything else collapsed into single '-'s,
+/// "Cats & Dogs: Part 2!" becomes "cats-dogs-part-2"
+pub fn slugify(s: &str) -> String {
+    let mut slug = String::new();
+    for c in s.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}